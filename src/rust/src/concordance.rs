@@ -0,0 +1,106 @@
+use extendr_api::prelude::*;
+use faer::Mat;
+
+use crate::labels::set_dimnames;
+use crate::parallel::with_threads;
+
+// tolerance for row sums of concordance matrices
+const TOLERANCE: f64 = 1e-8;
+
+#[allow(clippy::too_many_arguments)]
+#[extendr]
+/// @description
+/// Applies a concordance matrix to the elements of an input-output table, aggregating or disaggregating its sectors.
+///
+/// @param concordance
+/// A \eqn{n x m} concordance matrix, from the \eqn{n} sectors of the table to \eqn{m} new sectors.
+/// @param intermediate_transactions
+/// A \eqn{n x n} matrix of intermediate transactions.
+/// @param final_demand
+/// A \eqn{n x k} matrix of column vectors, such as final demand.
+/// @param value_added
+/// A \eqn{h x n} matrix of row vectors, such as total production, value added and satellite accounts.
+/// @param threads
+/// Number of threads. 0 means the session-wide setting.
+/// @param labels
+/// Optional labels of the new sectors.
+/// @param category_labels
+/// Optional labels of the column vectors.
+/// @param component_labels
+/// Optional labels of the row vectors.
+///
+/// @details
+/// Each row of the concordance matrix \eqn{C} holds the shares of a sector mapped to each new sector, so rows must sum
+/// up to one. Binary concordances aggregate sectors, weighted many-to-many concordances split a sector across several
+/// new sectors (e.g., when mapping between classifications) and one-to-many concordances disaggregate it by auxiliary
+/// weights. The new table is
+///
+/// \deqn{Z^* = C' Z C, \quad Y^* = C' Y, \quad W^* = W C}
+///
+/// which keeps row and column totals consistent with total production \eqn{x^* = x C}.
+///
+/// @return
+/// A list with the \eqn{m x m} intermediate transactions, \eqn{m x k} column vectors and \eqn{h x m} row vectors of
+/// the new table.
+///
+/// @noRd
+fn apply_concordance(
+  concordance: RMatrix<f64>,
+  intermediate_transactions: &[f64],
+  final_demand: RMatrix<f64>,
+  value_added: RMatrix<f64>,
+  threads: usize,
+  #[default = "NULL"] labels: Nullable<Strings>,
+  #[default = "NULL"] category_labels: Nullable<Strings>,
+  #[default = "NULL"] component_labels: Nullable<Strings>
+) -> Result<List> {
+
+  // get dimensions
+  let (n, m) = (concordance.nrows(), concordance.ncols());
+  if intermediate_transactions.len() != n * n {
+    return Err("concordance must have one row for each sector.".into());
+  }
+  if final_demand.nrows() != n || value_added.ncols() != n {
+    return Err("final_demand and value_added must have one element for each sector.".into());
+  }
+  let (k, h) = (final_demand.ncols(), value_added.nrows());
+
+  // create faer matrices
+  let concordance_matrix = Mat::from_fn(n, m, |row, col| concordance[[row, col]]);
+  let intermediate_transactions_matrix = Mat::from_fn(n, n, |row, col| intermediate_transactions[col * n + row]);
+  let final_demand_matrix = Mat::from_fn(n, k, |row, col| final_demand[[row, col]]);
+  let value_added_matrix = Mat::from_fn(h, n, |row, col| value_added[[row, col]]);
+
+  // each sector must be fully mapped
+  if let Some(row) = (0..n).find(|&row| {
+    let total: f64 = concordance_matrix.row(row).iter().sum();
+    (total - 1.0).abs() > TOLERANCE
+  }) {
+    return Err(format!("Row {} of concordance must sum up to one.", row + 1).into());
+  }
+
+  let (transactions, columns, rows) = with_threads(threads, || {
+    let transactions = concordance_matrix.transpose() * &intermediate_transactions_matrix * &concordance_matrix;
+    let columns = concordance_matrix.transpose() * &final_demand_matrix;
+    let rows = &value_added_matrix * &concordance_matrix;
+    (transactions, columns, rows)
+  })?;
+
+  Ok(list!(
+    intermediate_transactions = set_dimnames(
+      RArray::new_matrix(m, m, |row, col| transactions[(row, col)]),
+      &labels,
+      &labels
+    )?,
+    final_demand = set_dimnames(RArray::new_matrix(m, k, |row, col| columns[(row, col)]), &labels, &category_labels)?,
+    value_added = set_dimnames(RArray::new_matrix(h, m, |row, col| rows[(row, col)]), &component_labels, &labels)?
+  ))
+}
+
+// Macro to generate exports.
+// This ensures exported functions are registered with R.
+// See corresponding C code in `entrypoint.c`.
+extendr_module! {
+  mod concordance;
+  fn apply_concordance;
+}
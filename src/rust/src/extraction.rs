@@ -1,5 +1,41 @@
 use extendr_api::prelude::*;
 use faer::{linalg::solvers::Solve, Mat};
+use rayon::prelude::*;
+
+use crate::factorization::Factorization;
+use crate::labels::{fixed_labels, set_dimnames, set_names};
+use crate::parallel::with_threads;
+
+/// Differences in total output after extracting each sector, given by the Sherman-Morrison formula for the rank-one
+/// change of the base matrix: \eqn{1' x_{(-j)} = 1' x - x_j (m_j - 1) / b_{jj}}, where \eqn{m} are column (backward)
+/// or row (forward) sums and \eqn{b_{jj}} is the diagonal of the base inverse. Rows are labelled by sector and
+/// columns by `columns`.
+pub(crate) fn compute_extraction_differences(
+  output: &[f64],
+  sums: &[f64],
+  diagonal: &[f64],
+  total_production: &[f64],
+  labels: &Nullable<Strings>,
+  columns: &[&str]
+) -> Result<RArray<f64, [usize;2]>> {
+
+  // get dimensions
+  let n = output.len();
+  let sum_output = total_production.iter().sum::<f64>();
+  let sum_new_output = output.iter().sum::<f64>();
+
+  // diff in output after extracting each sector
+  let diff_output: Vec<f64> = (0..n)
+    .map(|j| sum_new_output - output[j] * (sums[j] - 1.0) / diagonal[j] - sum_output)
+    .collect();
+
+  // absolute and relative (to sum of total production) differences
+  set_dimnames(
+    RArray::new_matrix(n, 2, |row, col| if col == 0 { diff_output[row] } else { diff_output[row] / sum_output }),
+    labels,
+    &fixed_labels(columns)
+  )
+}
 
 #[extendr]
 /// Computes backward linkage extraction.
@@ -7,25 +43,36 @@ use faer::{linalg::solvers::Solve, Mat};
 /// @description
 /// Computes impact on demand structure after extracting a given sector \insertCite{miller_input-output_2009}{fio}.
 /// 
-/// @param technical_coefficients_matrix
-/// A nxn matrix of technical coefficients.
+/// @param leontief_factorization
+/// The factorization of the Leontief matrix, as returned by `compute_factorization()`.
 /// @param final_demand_matrix
 /// The final demand matrix.
 /// @param total_production
 /// A 1xn vector of total production.
+/// @param threads
+/// Number of threads. 0 means the session-wide setting.
+/// @param labels
+/// Optional labels of the sectors, attached as row names.
+/// 
+/// @details
+/// Extracting the demand structure of sector \eqn{j} is a rank-one change of the Leontief matrix, so the new output
+/// level follows from the Sherman-Morrison formula and the cached Leontief inverse, without decomposing the Leontief
+/// matrix again for each sector.
 /// 
 /// @references
 /// \insertAllCited{}
 /// 
 /// @noRd
 fn compute_extraction_backward(
-  technical_coefficients_matrix: &[f64],
+  leontief_factorization: ExternalPtr<Factorization>,
   final_demand_matrix: RMatrix<f64>,
-  total_production: &[f64]
-) -> RMatrix<f64> {
+  total_production: &[f64],
+  threads: usize,
+  #[default = "NULL"] labels: Nullable<Strings>
+) -> Result<RMatrix<f64>> {
 
   // get dimensions
-  let n = (technical_coefficients_matrix.len() as f64).sqrt() as usize;
+  let n = leontief_factorization.n();
   let n_fd = final_demand_matrix.nrows();
   let m_fd = final_demand_matrix.ncols();
   
@@ -35,39 +82,25 @@ fn compute_extraction_backward(
   .map(|x| x.iter().sum::<f64>())
   .collect();
 
-  // initialize objects
-  let mut backward_linkage = Mat::zeros(n, n);
-  let mut technical_coefficients_matrix_bl = Mat::from_fn(n, n, |row, col| technical_coefficients_matrix[col * n + row]);
-  let identity_matrix: &Mat<f64> = &Mat::identity(n, n);
-  let sum_output = total_production.iter().sum::<f64>();
-
-  // computes diff in output after extracting a sector demand structure
-  for j in 0..n {
-    // set j column to zero
-    for i in 0..n {
-      technical_coefficients_matrix_bl[(i, j)] = 0.0;
-    }
-    // calculate new Leontief matrix
-    let leontief_matrix = identity_matrix - &technical_coefficients_matrix_bl;
-    // calculate new Leontief inverse
-    let lu = leontief_matrix.partial_piv_lu();
-    let leontief_inverse = lu.solve(identity_matrix);
-    // calculate new output level
-    let new_output: Mat<f64> = leontief_inverse * Mat::from_fn(n, 1, |row, _| final_demand_rowsum[row]);
-    // calculate diff in output
-    let diff_output = new_output.col_iter().map(|x| x.iter().sum::<f64>()).sum::<f64>() - &sum_output;
-    // store diff in output
-    backward_linkage[(j, 0)] = diff_output;
-    // store relative backward linkage by dividing backward linkage by sum of total production
-    backward_linkage[(j, 1)] = diff_output / sum_output;
-    // reset j column to original values
-    for i in 0..n {
-      technical_coefficients_matrix_bl[(i, j)] = technical_coefficients_matrix[j * n + i];
-    }
-  }
+  // output level, column sums and diagonal of the Leontief inverse
+  let leontief_factorization: &Factorization = &leontief_factorization;
+  let (output, col_sums, diagonal) = with_threads(threads, || {
+    let leontief_inverse = leontief_factorization.inverse();
+    let output = leontief_factorization.solve(&final_demand_rowsum, false);
+    let col_sums: Vec<f64> = leontief_inverse.par_col_iter().map(|col| col.iter().sum::<f64>()).collect();
+    let diagonal: Vec<f64> = (0..n).map(|j| leontief_inverse[(j, j)]).collect();
+    (output, col_sums, diagonal)
+  })?;
 
-  // return backward linkage
-  RArray::new_matrix(n, 2, |rows, cols| backward_linkage[(rows, cols)])
+  // computes diff in output after extracting each sector demand structure
+  compute_extraction_differences(
+    &output,
+    &col_sums,
+    &diagonal,
+    total_production,
+    &labels,
+    &["backward_absolute", "backward_relative"]
+  )
 
 }
 
@@ -77,64 +110,59 @@ fn compute_extraction_backward(
 /// @description
 /// Computes impact on supply structure after extracting a given sector \insertCite{miller_input-output_2009}{fio}.
 /// 
-/// @param matrix A nxn matrix of technical or allocation coefficients.
+/// @param base_factorization The factorization of the base (Ghosh or Leontief) matrix, as returned by
+/// `compute_factorization()`.
 /// @param value_added_matrix The value-added matrix.
 /// @param total_production A 1xn vector of total production.
+/// @param threads Number of threads. 0 means the session-wide setting.
+/// @param labels Optional labels of the sectors, attached as row names.
+/// 
+/// @details
+/// Extracting the supply structure of sector \eqn{i} is a rank-one change of the base matrix, so the new output
+/// level follows from the Sherman-Morrison formula and the cached base inverse.
 /// 
 /// @references
 /// \insertAllCited{}
 /// 
 /// @noRd
 fn compute_extraction_forward(
-  matrix: &[f64],
+  base_factorization: ExternalPtr<Factorization>,
   value_added_matrix: RMatrix<f64>,
-  total_production: &[f64]
-) -> RMatrix<f64> {
+  total_production: &[f64],
+  threads: usize,
+  #[default = "NULL"] labels: Nullable<Strings>
+) -> Result<RMatrix<f64>> {
 
   // get dimensions
-  let n = (matrix.len() as f64).sqrt() as usize;
+  let n = base_factorization.n();
   let n_av = value_added_matrix.nrows();
   let m_av = value_added_matrix.ncols();
   
-  // get rowsum of value-added matrix
+  // get colsum of value-added matrix
   let value_added_colsum: Vec<f64> = Mat::from_fn(n_av, m_av, |row, col| value_added_matrix[[row, col]])
   .col_iter()
   .map(|x| x.iter().sum::<f64>())
   .collect();
 
-  // initialize objects
-  let mut forward_linkage = Mat::zeros(n, n);
-  let mut matrix_bl = Mat::from_fn(n, n, |row, col| matrix[col * n + row]);
-  let identity_matrix: &Mat<f64> = &Mat::identity(n, n);
-  let sum_output = total_production.iter().sum::<f64>();
-
-  // computes diff in output after extracting a sector demand structure
-  for i in 0..n {
-    // set j column to zero
-    for j in 0..n {
-      matrix_bl[(i, j)] = 0.0;
-    }
-    // calculate new base (Ghosh or Leontief) matrix
-    let base_matrix = identity_matrix - &matrix_bl;
-    // calculate new base inverse
-    let lu = base_matrix.partial_piv_lu();
-    let base_inverse = lu.solve(identity_matrix);
-    // calculate new output level
-    let new_output: Mat<f64> = Mat::from_fn(1, n, |_, col| value_added_colsum[col]) * base_inverse;
-    // calculate diff in output
-    let diff_output = new_output.col_iter().map(|x| x.iter().sum::<f64>()).sum::<f64>() - &sum_output;
-    // store diff in output
-    forward_linkage[(i, 0)] = diff_output;
-    // store relative forward linkage by dividing backward linkage by sum of total production
-    forward_linkage[(i, 1)] = diff_output / sum_output;
-    // reset j column to original values
-    for j in 0..n {
-      matrix_bl[(i, j)] = matrix[j * n + i];
-    }
-  }
+  // output level, row sums and diagonal of the base inverse
+  let base_factorization: &Factorization = &base_factorization;
+  let (output, row_sums, diagonal) = with_threads(threads, || {
+    let base_inverse = base_factorization.inverse();
+    let output = base_factorization.solve(&value_added_colsum, true);
+    let row_sums: Vec<f64> = base_inverse.par_row_iter().map(|row| row.iter().sum::<f64>()).collect();
+    let diagonal: Vec<f64> = (0..n).map(|i| base_inverse[(i, i)]).collect();
+    (output, row_sums, diagonal)
+  })?;
 
-  // return backward linkage
-  RArray::new_matrix(n, 2, |rows, cols| forward_linkage[(rows, cols)])
+  // computes diff in output after extracting each sector supply structure
+  compute_extraction_differences(
+    &output,
+    &row_sums,
+    &diagonal,
+    total_production,
+    &labels,
+    &["forward_absolute", "forward_relative"]
+  )
 
 }
 
@@ -142,6 +170,7 @@ fn compute_extraction_forward(
 /// Computes total impact after extracting a given sector.
 /// @param backward_linkage_matrix A nx2 matrix of backward linkage.
 /// @param forward_linkage_matrix A nx2 matrix of forward linkage.
+/// @param labels Optional labels of the sectors, attached as row names.
 /// @details
 /// Here we define total impact as the sum of impact on demand and supply structures
 /// after removal of a given sector.
@@ -177,8 +206,9 @@ fn compute_extraction_forward(
 /// @noRd
 fn compute_extraction_total(
   backward_linkage_matrix: RMatrix<f64>,
-  forward_linkage_matrix: RMatrix<f64>
-) -> RMatrix<f64> {
+  forward_linkage_matrix: RMatrix<f64>,
+  #[default = "NULL"] labels: Nullable<Strings>
+) -> Result<RMatrix<f64>> {
 
   // get dimensions
   let n_bl = backward_linkage_matrix.nrows();
@@ -197,8 +227,133 @@ fn compute_extraction_total(
   }
 
   // return total linkage
-  RArray::new_matrix(n_bl, 2, |rows, cols| total_linkage[(rows, cols)])
+  set_dimnames(
+    RArray::new_matrix(n_bl, 2, |rows, cols| total_linkage[(rows, cols)]),
+    &labels,
+    &fixed_labels(&["total_absolute", "total_relative"])
+  )
+
+}
+
+#[allow(clippy::too_many_arguments)]
+#[extendr]
+/// Computes regional extraction.
+///
+/// @description
+/// Computes output and value-added losses by country-sector of a multi-regional input-output model after extracting
+/// a country, or the intermediate trade between two countries \insertCite{miller_input-output_2009}{fio}.
+///
+/// @param tech_coeff
+/// A \eqn{n x n} matrix of technical coefficients.
+/// @param leontief_factorization
+/// The factorization of the Leontief matrix, as returned by `compute_factorization()`.
+/// @param final_demand
+/// A \eqn{1 x n} vector of final demand.
+/// @param value_added
+/// A \eqn{1 x n} vector of value added.
+/// @param total_production
+/// A \eqn{1 x n} vector of total production.
+/// @param n_countries
+/// Number of countries.
+/// @param country
+/// Index of the extracted country, starting at 1.
+/// @param threads
+/// Number of threads. 0 means the session-wide setting.
+/// @param partner
+/// Optional index of a partner country, starting at 1. If given, only intermediate trade between `country` and
+/// `partner` is extracted.
+/// @param labels
+/// Optional labels of the country-sectors, attached as names.
+///
+/// @details
+/// Sectors are ordered by country, so that the block \eqn{(r, s)} of technical coefficients holds intermediate sales
+/// of country \eqn{r} to country \eqn{s}. Extracting a country zeroes its row and column blocks, so that it neither
+/// buys nor sells intermediate inputs, while extracting a trade link zeroes the blocks \eqn{(r, s)} and \eqn{(s, r)}.
+/// Final demand is kept, and output after the extraction is \eqn{\bar{x} = (I - \bar{A})^{-1} f}. Value-added
+/// losses are output losses valued at the value-added coefficients of each country-sector.
+///
+/// @return
+/// A list with \eqn{1 x n} vectors of output before and after the extraction and of value-added losses.
+///
+/// @references
+/// \insertAllCited{}
+///
+/// @noRd
+fn compute_extraction_regional(
+  tech_coeff: &[f64],
+  leontief_factorization: ExternalPtr<Factorization>,
+  final_demand: &[f64],
+  value_added: &[f64],
+  total_production: &[f64],
+  n_countries: usize,
+  country: usize,
+  threads: usize,
+  #[default = "NULL"] partner: Nullable<usize>,
+  #[default = "NULL"] labels: Nullable<Strings>
+) -> Result<List> {
+
+  // get dimensions
+  let n = leontief_factorization.n();
+  if n_countries == 0 || n % n_countries != 0 {
+    return Err("Number of sectors must be a multiple of the number of countries.".into());
+  }
+  if tech_coeff.len() != n * n {
+    return Err("tech_coeff must have one row and one column for each sector.".into());
+  }
+  if final_demand.len() != n || value_added.len() != n || total_production.len() != n {
+    return Err("final_demand, value_added and total_production must have one element for each sector.".into());
+  }
+  let n_sectors = n / n_countries;
+
+  // extracted country and partner, zero-based
+  if country == 0 || country > n_countries {
+    return Err("country must be between 1 and the number of countries.".into());
+  }
+  let country = country - 1;
+  let partner = match partner {
+    NotNull(partner) if partner == 0 || partner > n_countries || partner - 1 == country => {
+      return Err("partner must be between 1 and the number of countries and differ from country.".into());
+    },
+    NotNull(partner) => Some(partner - 1),
+    Null => None,
+  };
+  let extracted = |i: usize, j: usize| {
+    let (origin, destination) = (i / n_sectors, j / n_sectors);
+    match partner {
+      Some(partner) => {
+        (origin == country && destination == partner) || (origin == partner && destination == country)
+      },
+      None => origin == country || destination == country,
+    }
+  };
+
+  // output before and after extraction
+  let leontief_factorization: &Factorization = &leontief_factorization;
+  let (output, extracted_output) = with_threads(threads, || {
+    let output = leontief_factorization.solve(final_demand, false);
+    let identity_matrix: Mat<f64> = Mat::identity(n, n);
+    let extracted_matrix = Mat::from_fn(n, n, |row, col| {
+      let coefficient = if extracted(row, col) { 0.0 } else { tech_coeff[col * n + row] };
+      identity_matrix[(row, col)] - coefficient
+    });
+    let final_demand_matrix = Mat::from_fn(n, 1, |row, _| final_demand[row]);
+    let extracted_output = extracted_matrix.partial_piv_lu().solve(&final_demand_matrix);
+    (output, extracted_output.col(0).iter().copied().collect::<Vec<f64>>())
+  })?;
+
+  // value-added losses, at value-added coefficients
+  let value_added_loss: Vec<f64> = (0..n)
+    .map(|j| {
+      let coefficient = if total_production[j] == 0.0 { 0.0 } else { value_added[j] / total_production[j] };
+      coefficient * (output[j] - extracted_output[j])
+    })
+    .collect();
 
+  Ok(list!(
+    output = set_names(output, &labels)?,
+    extracted_output = set_names(extracted_output, &labels)?,
+    value_added_loss = set_names(value_added_loss, &labels)?
+  ))
 }
 
 // Macro to generate exports.
@@ -209,4 +364,5 @@ extendr_module! {
   fn compute_extraction_backward;
   fn compute_extraction_forward;
   fn compute_extraction_total;
+  fn compute_extraction_regional;
 }
@@ -0,0 +1,165 @@
+use extendr_api::prelude::*;
+use faer::{
+  linalg::solvers::{PartialPivLu, Solve},
+  Mat,
+};
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::sync::OnceLock;
+
+use crate::labels::set_dimnames;
+use crate::parallel::with_threads;
+
+/// LU decomposition of \eqn{I - M} and its inverse, owned by Rust and handed to R as an external pointer. The inverse
+/// is only formed the first time it is needed.
+pub struct Factorization {
+  n: usize,
+  fingerprint: u64,
+  lu: PartialPivLu<f64>,
+  inverse: OnceLock<Mat<f64>>,
+}
+
+/// Hash of the coefficients, used to detect changes in the underlying matrix.
+fn fingerprint(coefficients: &[f64]) -> u64 {
+  let mut hasher = DefaultHasher::new();
+  coefficients.len().hash(&mut hasher);
+  coefficients.iter().for_each(|value| value.to_bits().hash(&mut hasher));
+  hasher.finish()
+}
+
+impl Factorization {
+  pub(crate) fn new(coefficients: &[f64]) -> Self {
+    // get dimensions
+    let n = (coefficients.len() as f64).sqrt() as usize;
+
+    // create faer matrix
+    let coefficients_matrix = Mat::from_fn(n, n, |row, col| coefficients[col * n + row]);
+
+    // decompose base matrix
+    let identity_matrix: Mat<f64> = Mat::identity(n, n);
+    let base_matrix = &identity_matrix - coefficients_matrix;
+    let lu = base_matrix.partial_piv_lu();
+
+    Factorization { n, fingerprint: fingerprint(coefficients), lu, inverse: OnceLock::new() }
+  }
+
+  pub(crate) fn n(&self) -> usize {
+    self.n
+  }
+
+  /// Inverse of \eqn{I - M}, solved from the LU decomposition on first use.
+  pub(crate) fn inverse(&self) -> &Mat<f64> {
+    self.inverse.get_or_init(|| self.lu.solve(Mat::<f64>::identity(self.n, self.n)))
+  }
+
+  /// Inverse of \eqn{I - M}, formed in a scoped thread pool if it is not formed yet.
+  pub(crate) fn inverse_in_pool(&self, threads: usize) -> Result<&Mat<f64>> {
+    with_threads(threads, || self.inverse())
+  }
+
+  /// Solves \eqn{(I - M) x = b}, or \eqn{(I - M)' x = b} if `transpose` is true.
+  pub(crate) fn solve(&self, rhs: &[f64], transpose: bool) -> Vec<f64> {
+    let rhs = Mat::from_fn(self.n, 1, |row, _| rhs[row]);
+    let solution = if transpose {
+      self.lu.solve_transpose(rhs)
+    } else {
+      self.lu.solve(rhs)
+    };
+    solution.col(0).iter().copied().collect()
+  }
+
+  /// Inverse as a column-major vector, as it would be read from R.
+  pub(crate) fn inverse_col_major(&self) -> Vec<f64> {
+    let inverse = self.inverse();
+    (0..self.n * self.n).map(|index| inverse[(index % self.n, index / self.n)]).collect()
+  }
+}
+
+#[extendr]
+/// @description
+/// Computes the LU decomposition of \eqn{I - M} for a matrix of technical or allocation coefficients.
+///
+/// @param coefficients
+/// A \eqn{n x n} matrix of technical or allocation coefficients.
+/// @param threads
+/// Number of threads. 0 means the session-wide setting.
+///
+/// @details
+/// The decomposition is kept by Rust and returned to R as an external pointer, so downstream computations (inverses,
+/// field of influence, extractions and linkages) reuse it instead of decomposing the base matrix again. The Leontief
+/// or Ghosh inverse is only formed, and then kept along with the decomposition, when a computation first needs it,
+/// so computations that only solve linear systems never pay for it. A fingerprint of the coefficients is stored along with the decomposition,
+/// so it can be checked for staleness with `is_factorization_valid()`.
+///
+/// @return
+/// An external pointer to the factorization.
+///
+/// @noRd
+fn compute_factorization(coefficients: &[f64], threads: usize) -> Result<ExternalPtr<Factorization>> {
+  let factorization = with_threads(threads, || Factorization::new(coefficients))?;
+
+  Ok(ExternalPtr::new(factorization))
+}
+
+#[extendr]
+/// @description
+/// Checks whether a factorization is still valid for a matrix of coefficients.
+///
+/// @param factorization
+/// An external pointer, as returned by `compute_factorization()`.
+/// @param coefficients
+/// A \eqn{n x n} matrix of technical or allocation coefficients.
+///
+/// @return
+/// `TRUE` if the factorization was computed from the same coefficients, `FALSE` if the coefficients changed or the
+/// pointer is no longer valid (e.g., after the object was serialized).
+///
+/// @noRd
+fn is_factorization_valid(factorization: Robj, coefficients: &[f64]) -> bool {
+  match ExternalPtr::<Factorization>::try_from(&factorization) {
+    Ok(factorization) => {
+      factorization.n * factorization.n == coefficients.len()
+        && factorization.fingerprint == fingerprint(coefficients)
+    },
+    Err(_) => false,
+  }
+}
+
+#[extendr]
+/// @description
+/// Gets the inverse of \eqn{I - M} from a factorization.
+///
+/// @param factorization
+/// An external pointer, as returned by `compute_factorization()`.
+/// @param threads
+/// Number of threads used to form the inverse, if not formed yet. 0 means the session-wide setting.
+/// @param row_labels
+/// Optional labels of the rows (sectors), attached to the result.
+/// @param col_labels
+/// Optional labels of the columns (sectors), attached to the result.
+///
+/// @return
+/// A \eqn{n x n} Leontief or Ghosh inverse matrix.
+///
+/// @noRd
+fn get_factorization_inverse(
+  factorization: ExternalPtr<Factorization>,
+  threads: usize,
+  #[default = "NULL"] row_labels: Nullable<Strings>,
+  #[default = "NULL"] col_labels: Nullable<Strings>
+) -> Result<RArray<f64, [usize;2]>> {
+  let n = factorization.n();
+  let inverse = factorization.inverse_in_pool(threads)?;
+
+  // convert to R matrix
+  set_dimnames(RArray::new_matrix(n, n, |row, col| inverse[(row, col)]), &row_labels, &col_labels)
+}
+
+// Macro to generate exports.
+// This ensures exported functions are registered with R.
+// See corresponding C code in `entrypoint.c`.
+extendr_module! {
+  mod factorization;
+  fn compute_factorization;
+  fn is_factorization_valid;
+  fn get_factorization_inverse;
+}
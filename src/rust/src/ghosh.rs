@@ -2,6 +2,9 @@ use faer::{Mat, linalg::solvers::Solve};
 use extendr_api::prelude::*;
 use rayon::prelude::*;
 
+use crate::labels::set_dimnames;
+use crate::parallel::with_threads;
+
 #[extendr]
 /// Computes allocation coefficients matrix.
 /// 
@@ -9,6 +12,10 @@ use rayon::prelude::*;
 /// A nxn matrix of intermediate transactions.
 /// @param total_production
 /// A 1xn vector of total production.
+/// @param row_labels
+/// Optional labels of the rows (sectors), attached to the result.
+/// @param col_labels
+/// Optional labels of the columns (sectors), attached to the result.
 /// 
 /// @details
 /// Allocation coefficients matrix is the rowwise ratio of
@@ -33,7 +40,9 @@ use rayon::prelude::*;
 fn compute_allocation_coeff(
   intermediate_transactions: &[f64],
   total_production: &[f64],
-) -> RArray<f64, [usize;2]> {
+  #[default = "NULL"] row_labels: Nullable<Strings>,
+  #[default = "NULL"] col_labels: Nullable<Strings>
+) -> Result<RArray<f64, [usize;2]>> {
   
   // get dimensions (square root of length)
   let n = (intermediate_transactions.len() as f64).sqrt() as usize;
@@ -45,7 +54,11 @@ fn compute_allocation_coeff(
     .map(|(i, value)| value / total_production[i % n])
     .collect();
 
-  RArray::new_matrix(n, n, |row, column| allocation_coeff[row + column * n])
+  set_dimnames(
+    RArray::new_matrix(n, n, |row, column| allocation_coeff[row + column * n]),
+    &row_labels,
+    &col_labels
+  )
 }
 
 #[extendr]
@@ -53,6 +66,12 @@ fn compute_allocation_coeff(
 /// 
 /// @param allocation_coeff
 /// A \eqn{n x n} matrix of allocation coefficients.
+/// @param threads
+/// Number of threads. 0 means the session-wide setting.
+/// @param row_labels
+/// Optional labels of the rows (sectors), attached to the result.
+/// @param col_labels
+/// Optional labels of the columns (sectors), attached to the result.
 /// 
 /// @details
 /// The Ghosh inverse matrix is the inverse of the
@@ -66,21 +85,28 @@ fn compute_allocation_coeff(
 /// \insertAllCited{}
 /// 
 /// @noRd
-fn compute_ghosh_inverse(allocation_coeff: &[f64]) -> RArray<f64, [usize;2]> {
+fn compute_ghosh_inverse(
+  allocation_coeff: &[f64],
+  threads: usize,
+  #[default = "NULL"] row_labels: Nullable<Strings>,
+  #[default = "NULL"] col_labels: Nullable<Strings>
+) -> Result<RArray<f64, [usize;2]>> {
 
   // get dimensions
   let n = (allocation_coeff.len() as f64).sqrt() as usize;
 
-  // create faer matrix
-  let allocation_coeff_matrix = Mat::from_fn(n, n, |row, col| allocation_coeff[col * n + row]);
+  let ghosh_inverse = with_threads(threads, || {
+    // create faer matrix
+    let allocation_coeff_matrix = Mat::from_fn(n, n, |row, col| allocation_coeff[col * n + row]);
 
-  // calculate Ghosh inverse
-  let identity_matrix: Mat<f64> = Mat::identity(n, n);
-  let ghosh_matrix = &identity_matrix - allocation_coeff_matrix;
-  let ghosh_inverse = ghosh_matrix.partial_piv_lu().solve(identity_matrix);
+    // calculate Ghosh inverse
+    let identity_matrix: Mat<f64> = Mat::identity(n, n);
+    let ghosh_matrix = &identity_matrix - allocation_coeff_matrix;
+    ghosh_matrix.partial_piv_lu().solve(identity_matrix)
+  })?;
 
   // convert to R matrix
-  RArray::new_matrix(n, n, |row, col| ghosh_inverse[(row, col)])
+  set_dimnames(RArray::new_matrix(n, n, |row, col| ghosh_inverse[(row, col)]), &row_labels, &col_labels)
 }
 
 // Macro to generate exports.
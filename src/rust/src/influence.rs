@@ -1,5 +1,9 @@
 use extendr_api::prelude::*;
-use faer::{linalg::solvers::Solve, Mat};
+use faer::Mat;
+
+use crate::factorization::Factorization;
+use crate::labels::set_dimnames;
+use crate::progress::run_in_chunks;
 
 #[extendr]
 /// @description
@@ -15,9 +19,22 @@ use faer::{linalg::solvers::Solve, Mat};
 /// specifically, which coefficients, when altered, would have the greatest
 /// impact on the system as a whole \insertCite{vale_alise_2020}{fio}.
 /// 
-/// @param tech_coeff_matrix A nxn matrix of technical coefficients.
-/// @param leontief_inverse_matrix The open model nxn Leontief inverse matrix.
+/// Since adding \eqn{\epsilon} to coefficient \eqn{a_{ij}} is a rank-one change of the Leontief matrix, the new
+/// Leontief inverse follows from the Sherman-Morrison formula:
+/// 
+/// \deqn{L^{(ij)} - L = \frac{\epsilon L_{\cdot i} L_{j \cdot}}{1 - \epsilon l_{ji}}}
+/// 
+/// so no further decomposition of the Leontief matrix is needed.
+/// 
+/// The field of influence is computed in chunks of columns. Between chunks, the computation can be interrupted by the
+/// user and progress is reported to the `progress` callback.
+/// 
+/// @param leontief_factorization The factorization of the Leontief matrix, as returned by `compute_factorization()`.
 /// @param epsilon The epsilon value.
+/// @param threads Number of threads. 0 means the session-wide setting.
+/// @param progress A function called with the number of sectors done and the total number of sectors, or `NULL`.
+/// @param row_labels Optional labels of the rows (sectors), attached to the result.
+/// @param col_labels Optional labels of the columns (sectors), attached to the result.
 ///
 /// @return Field of influence matrix.
 /// 
@@ -38,56 +55,40 @@ use faer::{linalg::solvers::Solve, Mat};
 /// my_iom$field_influence
 /// 
 /// @noRd
-
 fn compute_field_influence(
-  tech_coeff_matrix: &[f64],
-  leontief_inverse_matrix: &[f64],
-  epsilon: f64
-) -> RArray<f64, [usize;2]> {
+  leontief_factorization: ExternalPtr<Factorization>,
+  epsilon: f64,
+  threads: usize,
+  #[default = "NULL"] progress: Nullable<Function>,
+  #[default = "NULL"] row_labels: Nullable<Strings>,
+  #[default = "NULL"] col_labels: Nullable<Strings>
+) -> Result<RArray<f64, [usize;2]>> {
   
   // get dimensions
-  let n = (leontief_inverse_matrix.len() as f64).sqrt() as usize;
+  let n = leontief_factorization.n();
+  let leontief_inverse_matrix = leontief_factorization.inverse_in_pool(threads)?;
 
-  // create faer matrix
-  let tech_coeff_matrix = Mat::from_fn(n, n, |row, col| tech_coeff_matrix[col * n + row]);
-  let leontief_inverse_matrix = Mat::from_fn(n, n, |row, col| leontief_inverse_matrix[col * n + row]);
-  let mut incremental_matrix = Mat::zeros(n, n);
-  let mut influence_matrix = Mat::zeros(n, n);
+  // squared elements of the Leontief inverse
+  let squared_leontief = Mat::from_fn(n, n, |row, col| leontief_inverse_matrix[(row, col)].powi(2));
 
-  // loop to calculate influence matrix
-  for i in 0..n {
-    for j in 0..n {
-      // create incremental matrix
-      incremental_matrix[(i, j)] = epsilon;
-      // calculate new technical coefficients matrix
-      let new_tech_coeff_matrix = &tech_coeff_matrix + &incremental_matrix;
-      // identity matrix
-      let identity_matrix: Mat<f64> = Mat::identity(n, n);
-      // calculate new Leontief matrix
-      let new_leontief_matrix = &identity_matrix - new_tech_coeff_matrix;
-      
-      // calculate new Leontief inverse
-      let lu = new_leontief_matrix.partial_piv_lu();
-      let new_leontief_inverse = lu.solve(identity_matrix);
+  // squared Sherman-Morrison denominators, w_ij = 1 / (1 - epsilon * l_ji)^2
+  let weights = Mat::from_fn(n, n, |i, j| (1.0 - epsilon * leontief_inverse_matrix[(j, i)]).powi(-2));
 
-      // calculate field of influence
-      let mut influence = new_leontief_inverse - &leontief_inverse_matrix;
-      for x in 0..n {
-        for y in 0..n {
-          // calculate field of influence
-          influence[(x, y)] = f64::powf(influence[(x, y)] / epsilon, 2.0);
-          //sum elements
-          influence_matrix[(x, y)] += influence[(x, y)];
-        }
-      }
-      // reset incremental matrix
-      incremental_matrix[(i, j)] = 0.0; 
-    }
+  // sum of squared changes over all perturbed coefficients, f_xy = sum_ij l_xi^2 w_ij l_jy^2, by chunks of columns
+  let chunks = run_in_chunks(n, threads, &progress, |cols| {
+    let squared_leontief_cols = squared_leontief.subcols(cols.start, cols.len());
+    &squared_leontief * (&weights * squared_leontief_cols)
+  })?;
+
+  let mut influence_matrix = Mat::zeros(n, n);
+  let mut col = 0;
+  for chunk in chunks {
+    influence_matrix.subcols_mut(col, chunk.ncols()).copy_from(&chunk);
+    col += chunk.ncols();
   }
 
   // convert to R matrix
-  let influence_matrix_r = RArray::new_matrix(n, n, |r, c| influence_matrix[(c, r)]);
-  influence_matrix_r
+  set_dimnames(RArray::new_matrix(n, n, |r, c| influence_matrix[(c, r)]), &row_labels, &col_labels)
 
 }
 
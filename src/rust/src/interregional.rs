@@ -0,0 +1,82 @@
+use extendr_api::prelude::*;
+use rayon::prelude::*;
+
+#[extendr]
+/// @description
+/// Builds the multi-regional technical coefficients matrix under the Chenery-Moses assumption.
+///
+/// @param regional_tech_coeff
+/// A \eqn{n x n x r} array of regional technical coefficients, one \eqn{n x n} slice per region.
+/// @param trade_shares
+/// A \eqn{n x r x r} array of interregional trade coefficients. Element \eqn{[i, r, s]} is the share of
+/// commodity \eqn{i} used in region \eqn{s} that is supplied by region \eqn{r}.
+/// @param n_regions
+/// Number of regions.
+///
+/// @details
+/// In the interregional model proposed by Chenery and Moses, each region has its own technology,
+/// described by a \eqn{n x n} matrix of regional technical coefficients \eqn{A^s}, while the origin of
+/// the inputs is given by trade coefficients that only depend on the commodity and on the pair of
+/// regions \insertCite{miller_input-output_2009}{fio}. The multi-regional technical coefficients matrix
+/// is then assembled block by block as:
+///
+/// \deqn{A^{rs} = \hat{c}^{rs} A^s}
+///
+/// where \eqn{\hat{c}^{rs}} is a diagonal matrix with the shares of each commodity used in region
+/// \eqn{s} that comes from region \eqn{r}. Resulting matrix follows the country-sector layout of `miom`
+/// objects, i.e., Region1_Sector1, Region1_Sector2, ..., Region2_Sector1 etc.
+///
+/// @return
+/// A \eqn{nr x nr} multi-regional matrix of technical coefficients.
+///
+/// @references
+/// \insertAllCited{}
+///
+/// @examples
+/// # two regions, two sectors
+/// regional_tech_coeff <- array(c(0.1, 0.2, 0.3, 0.1, 0.2, 0.1, 0.1, 0.3), c(2, 2, 2))
+/// trade_shares <- array(c(0.8, 0.7, 0.2, 0.3, 0.1, 0.4, 0.9, 0.6), c(2, 2, 2))
+/// fio:::compute_tech_coeff_chenery_moses(regional_tech_coeff, trade_shares, 2L)
+///
+/// @noRd
+fn compute_tech_coeff_chenery_moses(
+  regional_tech_coeff: &[f64],
+  trade_shares: &[f64],
+  n_regions: usize
+) -> Result<RArray<f64, [usize;2]>> {
+
+  // get dimensions
+  if n_regions == 0 {
+    return Err("n_regions must be positive.".into());
+  }
+  let n = ((regional_tech_coeff.len() / n_regions) as f64).sqrt() as usize;
+  if regional_tech_coeff.len() != n_regions * n * n {
+    return Err("regional_tech_coeff must have one n x n slice for each region.".into());
+  }
+  if trade_shares.len() != n * n_regions * n_regions {
+    return Err("trade_shares must be a n x r x r array, matching regional_tech_coeff.".into());
+  }
+  let n_total = n * n_regions;
+
+  // A^{rs}_{ij} = c_i^{rs} * a^s_{ij}, stored column-major
+  let tech_coeff: Vec<f64> = (0..n_total * n_total)
+    .into_par_iter()
+    .map(|index| {
+      let (row, col) = (index % n_total, index / n_total);
+      let (origin, i) = (row / n, row % n);
+      let (destination, j) = (col / n, col % n);
+      let share = trade_shares[i + origin * n + destination * n * n_regions];
+      share * regional_tech_coeff[i + j * n + destination * n * n]
+    })
+    .collect();
+
+  Ok(RArray::new_matrix(n_total, n_total, |row, col| tech_coeff[row + col * n_total]))
+}
+
+// Macro to generate exports.
+// This ensures exported functions are registered with R.
+// See corresponding C code in `entrypoint.c`.
+extendr_module! {
+  mod interregional;
+  fn compute_tech_coeff_chenery_moses;
+}
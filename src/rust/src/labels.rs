@@ -0,0 +1,73 @@
+use extendr_api::prelude::*;
+
+/// Converts labels into an R object, checking that there is one label for each of `n` rows or columns. `NULL` labels
+/// are kept as R `NULL`, leaving the dimension unnamed.
+fn labels_robj(labels: &Nullable<Strings>, n: usize) -> Result<Robj> {
+  match labels {
+    NotNull(labels) if labels.len() != n => {
+      Err(format!("Expected {} labels, got {}.", n, labels.len()).into())
+    },
+    NotNull(labels) => Ok(labels.clone().into()),
+    Null => Ok(Robj::from(())),
+  }
+}
+
+/// Converts fixed labels, such as the names of the columns of a result, into nullable labels.
+pub(crate) fn fixed_labels(labels: &[&str]) -> Nullable<Strings> {
+  NotNull(Strings::from_values(labels))
+}
+
+/// Attaches row and column labels to a matrix as its dimnames.
+///
+/// Lengths are checked before setting the attribute, since R would signal the mismatch by unwinding through Rust
+/// frames.
+pub(crate) fn set_dimnames(
+  mut matrix: RMatrix<f64>,
+  row_labels: &Nullable<Strings>,
+  col_labels: &Nullable<Strings>
+) -> Result<RMatrix<f64>> {
+  if matches!((row_labels, col_labels), (Null, Null)) {
+    return Ok(matrix);
+  }
+  let rows = labels_robj(row_labels, matrix.nrows())?;
+  let cols = labels_robj(col_labels, matrix.ncols())?;
+  matrix.set_dimnames(List::from_values([rows, cols]));
+  Ok(matrix)
+}
+
+/// Attaches labels to each dimension of a 3-dimensional array as its dimnames.
+pub(crate) fn set_dimnames_3d(
+  mut array: RArray<f64, [usize;3]>,
+  labels: [&Nullable<Strings>; 3]
+) -> Result<RArray<f64, [usize;3]>> {
+  if labels.iter().all(|labels| matches!(labels, Null)) {
+    return Ok(array);
+  }
+  let dims = *array.dim();
+  let dimnames = labels
+    .iter()
+    .zip(dims.iter())
+    .map(|(labels, &n)| labels_robj(labels, n))
+    .collect::<Result<Vec<Robj>>>()?;
+  array.set_attrib(dimnames_symbol(), List::from_values(dimnames))?;
+  Ok(array)
+}
+
+/// Attaches labels to a vector as its names.
+pub(crate) fn set_names(values: Vec<f64>, labels: &Nullable<Strings>) -> Result<Doubles> {
+  let names = labels_robj(labels, values.len())?;
+  let mut vector = Doubles::from_values(values);
+  if !names.is_null() {
+    vector.set_attrib(names_symbol(), names)?;
+  }
+  Ok(vector)
+}
+
+/// Attaches labels to the elements of a list as its names.
+pub(crate) fn set_list_names(mut list: List, labels: &Nullable<Strings>) -> Result<List> {
+  let names = labels_robj(labels, list.len())?;
+  if !names.is_null() {
+    list.set_attrib(names_symbol(), names)?;
+  }
+  Ok(list)
+}
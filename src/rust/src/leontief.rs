@@ -2,6 +2,9 @@ use faer::{Mat, linalg::solvers::Solve};
 use extendr_api::prelude::*;
 use rayon::prelude::*;
 
+use crate::labels::{fixed_labels, set_dimnames};
+use crate::parallel::with_threads;
+
 #[extendr]
 /// @description
 /// Computes technical coefficients matrix.
@@ -10,6 +13,10 @@ use rayon::prelude::*;
 /// A \eqn{n x n} matrix of intermediate transactions.
 /// @param total_production
 /// A \eqn{1 x n} vector of total production.
+/// @param row_labels
+/// Optional labels of the rows (sectors), attached to the result.
+/// @param col_labels
+/// Optional labels of the columns (sectors), attached to the result.
 /// 
 /// @details
 /// It computes the technical coefficients matrix, a \eqn{n x n} matrix known as `A` matrix which is the column-wise
@@ -38,13 +45,14 @@ use rayon::prelude::*;
 /// my_iom$technical_coefficients_matrix
 /// 
 /// @noRd
-
 fn compute_tech_coeff(
   // There's an optional faer feature in extendr-api but it's not working (for the time I'm writing this)
   // see https://github.com/extendr/extendr/discussions/804
   intermediate_transactions: &[f64],
   total_production: &[f64],
-) -> RArray<f64, [usize;2]> {
+  #[default = "NULL"] row_labels: Nullable<Strings>,
+  #[default = "NULL"] col_labels: Nullable<Strings>
+) -> Result<RArray<f64, [usize;2]>> {
   
   // get dimensions (square root of length)
   let n = (intermediate_transactions.len() as f64).sqrt() as usize;
@@ -56,7 +64,11 @@ fn compute_tech_coeff(
     .map(|(i, value)| value / total_production[i / n])
     .collect();
 
-  RArray::new_matrix(n, n, |row, column| tech_coeff[row + column * n])
+  set_dimnames(
+    RArray::new_matrix(n, n, |row, column| tech_coeff[row + column * n]),
+    &row_labels,
+    &col_labels
+  )
 }
 
 #[extendr]
@@ -65,6 +77,12 @@ fn compute_tech_coeff(
 /// 
 /// @param tech_coeff
 /// A \eqn{n x n} matrix of technical coefficients.
+/// @param threads
+/// Number of threads. 0 means the session-wide setting.
+/// @param row_labels
+/// Optional labels of the rows (sectors), attached to the result.
+/// @param col_labels
+/// Optional labels of the columns (sectors), attached to the result.
 /// 
 /// @details
 /// It computes the Leontief inverse matrix \insertCite{leontief_economia_1983}{fio}, which is the inverse of the
@@ -102,24 +120,146 @@ fn compute_tech_coeff(
 /// my_iom$leontief_inverse_matrix
 /// 
 /// @noRd
-
-fn compute_leontief_inverse(tech_coeff: &[f64]) -> RArray<f64, [usize;2]> {
+fn compute_leontief_inverse(
+  tech_coeff: &[f64],
+  threads: usize,
+  #[default = "NULL"] row_labels: Nullable<Strings>,
+  #[default = "NULL"] col_labels: Nullable<Strings>
+) -> Result<RArray<f64, [usize;2]>> {
 
   // get dimensions
   let n = (tech_coeff.len() as f64).sqrt() as usize;
 
-  // create faer matrix
-  let tech_coeff_matrix = Mat::from_fn(n, n, |row, col| tech_coeff[col * n + row]);
+  let leontief_inverse = with_threads(threads, || {
+    // create faer matrix
+    let tech_coeff_matrix = Mat::from_fn(n, n, |row, col| tech_coeff[col * n + row]);
 
-  // calculate Leontief matrix
-  let identity_matrix: Mat<f64> = Mat::identity(n, n);
-  let leontief_matrix = &identity_matrix - tech_coeff_matrix;
+    // calculate Leontief matrix
+    let identity_matrix: Mat<f64> = Mat::identity(n, n);
+    let leontief_matrix = &identity_matrix - tech_coeff_matrix;
 
-  // calculate Leontief inverse
-  let leontief_inverse = leontief_matrix.partial_piv_lu().solve(identity_matrix);
+    // calculate Leontief inverse
+    leontief_matrix.partial_piv_lu().solve(identity_matrix)
+  })?;
 
   // convert to R matrix
-  RArray::new_matrix(n, n, |row, col| leontief_inverse[(row, col)])
+  set_dimnames(RArray::new_matrix(n, n, |row, col| leontief_inverse[(row, col)]), &row_labels, &col_labels)
+}
+
+/// Production layers \eqn{x^{(k)} = A x^{(k-1)}} of a final demand vector, given the product by the technical
+/// coefficients, along with the residual of the truncated series and whether it converged.
+pub(crate) fn production_layers<F>(
+  multiply: F,
+  final_demand: &[f64],
+  max_order: usize,
+  tolerance: f64
+) -> (Vec<Mat<f64>>, f64, bool)
+where
+  F: Fn(&Mat<f64>) -> Mat<f64>,
+{
+  // initial effect
+  let n = final_demand.len();
+  let mut layers: Vec<Mat<f64>> = vec![Mat::from_fn(n, 1, |row, _| final_demand[row])];
+  let mut cumulative: f64 = final_demand.iter().map(|value| value.abs()).sum();
+  let mut next_layer = multiply(&layers[0]);
+  let mut residual: f64 = next_layer.col(0).iter().map(|value| value.abs()).sum();
+
+  // successive rounds until convergence or max order
+  while residual > tolerance * cumulative && layers.len() <= max_order {
+    cumulative += residual;
+    let layer = next_layer;
+    next_layer = multiply(&layer);
+    residual = next_layer.col(0).iter().map(|value| value.abs()).sum();
+    layers.push(layer);
+  }
+  let converged = residual <= tolerance * cumulative;
+
+  (layers, residual, converged)
+}
+
+/// Production layers as a list with a \eqn{n x (K + 1)} matrix, labelled by sector and round, the residual and
+/// whether the series converged.
+pub(crate) fn production_layers_list(
+  layers: &[Mat<f64>],
+  residual: f64,
+  converged: bool,
+  labels: &Nullable<Strings>
+) -> Result<List> {
+  let n = layers[0].nrows();
+  let rounds: Vec<String> = (0..layers.len()).map(|round| format!("round_{}", round)).collect();
+  let rounds: Vec<&str> = rounds.iter().map(|round| round.as_str()).collect();
+  let layers_matrix = set_dimnames(
+    RArray::new_matrix(n, layers.len(), |row, col| layers[col][(row, 0)]),
+    labels,
+    &fixed_labels(&rounds)
+  )?;
+
+  Ok(list!(layers = layers_matrix, residual = residual, converged = converged))
+}
+
+#[extendr]
+/// @description
+/// Computes production layers of a final demand vector through the power series of the Leontief inverse.
+/// 
+/// @param tech_coeff
+/// A \eqn{n x n} matrix of technical coefficients.
+/// @param final_demand
+/// A \eqn{n x 1} vector of final demand.
+/// @param max_order
+/// Maximum number of rounds of intermediate demand.
+/// @param tolerance
+/// Convergence criterion. Iterations stop when the residual falls below this share of total output.
+/// @param threads
+/// Number of threads. 0 means the session-wide setting.
+/// @param labels
+/// Optional labels of the sectors, attached as row names.
+/// 
+/// @details
+/// The Leontief inverse can be expanded into a power series \insertCite{miller_input-output_2009}{fio}:
+/// 
+/// \deqn{L = (I - A)^{-1} = I + A + A^2 + A^3 + \dots}
+/// 
+/// So output required by final demand \eqn{f} is the sum of production layers \eqn{x^{(0)} = f} and
+/// \eqn{x^{(k)} = A x^{(k-1)}}, which are the initial effect and the successive rounds of intermediate demand.
+/// 
+/// Since \eqn{(I - A) \sum_{k=0}^{K} x^{(k)} = f - A x^{(K)}}, the residual of the truncated series is the
+/// sum of absolute values of the next layer, which is reported along with a convergence flag. Only matrix-vector
+/// products are needed, so it is also an iterative alternative to LU decomposition for very large tables. For sparse
+/// tables, see `compute_production_layers_sparse()`.
+/// 
+/// @return
+/// A list with a \eqn{n x (K + 1)} matrix of production layers, with columns named after rounds, the residual and
+/// whether the series converged.
+/// 
+/// @references
+/// \insertAllCited{}
+/// 
+/// @noRd
+fn compute_production_layers(
+  tech_coeff: &[f64],
+  final_demand: &[f64],
+  max_order: usize,
+  tolerance: f64,
+  threads: usize,
+  #[default = "NULL"] labels: Nullable<Strings>
+) -> Result<List> {
+
+  // get dimensions
+  let n = final_demand.len();
+
+  // check dimensions
+  if tech_coeff.len() != n * n {
+    return Err("tech_coeff must be a n x n matrix, matching final_demand.".into());
+  }
+
+  let (layers, residual, converged) = with_threads(threads, || {
+    // create faer matrix
+    let tech_coeff_matrix = Mat::from_fn(n, n, |row, col| tech_coeff[col * n + row]);
+
+    production_layers(|layer| &tech_coeff_matrix * layer, final_demand, max_order, tolerance)
+  })?;
+
+  production_layers_list(&layers, residual, converged, &labels)
 }
 
 // Macro to generate exports.
@@ -129,4 +269,5 @@ extendr_module! {
   mod leontief;
   fn compute_tech_coeff;
   fn compute_leontief_inverse;
+  fn compute_production_layers;
 }
\ No newline at end of file
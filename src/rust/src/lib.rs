@@ -1,11 +1,24 @@
 // module imports
+mod concordance;
 mod extraction;
+mod factorization;
 mod ghosh;
 mod influence;
+mod interregional;
+mod labels;
 mod leontief;
 mod linkages;
+mod mrio;
 mod multipliers;
 mod parallel;
+mod position;
+mod progress;
+mod satellite;
+mod sda;
+mod spa;
+mod sparse;
+mod sut;
+mod validation;
 
 use extendr_api::prelude::*;
 
@@ -21,4 +34,15 @@ extendr_module! {
   use ghosh;
   use extraction;
   use parallel;
+  use interregional;
+  use sda;
+  use spa;
+  use sparse;
+  use factorization;
+  use satellite;
+  use mrio;
+  use position;
+  use sut;
+  use concordance;
+  use validation;
 }
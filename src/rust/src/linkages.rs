@@ -2,6 +2,10 @@ use extendr_api::prelude::*;
 use rayon::prelude::*;
 use faer::Mat;
 
+use crate::factorization::Factorization;
+use crate::labels::{fixed_labels, set_dimnames, set_names};
+use crate::parallel::with_threads;
+
 #[extendr]
 /// Computes average of all elements of a nxn matrix
 /// @param matrix A nxn matrix.
@@ -59,12 +63,8 @@ fn compute_col_average(
     .collect()
 }
 
-#[extendr]
-/// @description Computes sensitivity of dispersion
-/// @param matrix A nxn matrix of Leontief or Ghosh inverse.
-/// @return A vector of sensitivity of dispersion.
-/// @noRd
-fn compute_sensitivity_dispersion(
+/// Sensitivity of dispersion, row averages of the matrix over its overall average.
+fn sensitivity_dispersion(
   matrix: &[f64]
 ) -> Vec<f64> {
   
@@ -78,12 +78,8 @@ fn compute_sensitivity_dispersion(
   rows_average.par_iter().map(|x| x / matrix_average).collect()
 }
 
-#[extendr]
-/// Computes power of dispersion
-/// @param leontief_inverse_matrix A nxn matrix of Leontief inverse.
-/// @return A vector of power of dispersion.
-/// @noRd
-fn compute_power_dispersion(
+/// Power of dispersion, column averages of the Leontief inverse over its overall average.
+fn power_dispersion(
   leontief_inverse_matrix: &[f64]
 ) -> Vec<f64> {
   
@@ -97,12 +93,8 @@ fn compute_power_dispersion(
   cols_average.par_iter().map(|x| x / leontief_average).collect::<Vec<f64>>()
 }
 
-#[extendr]
-/// Computes power of dispersion coefficients of variation
-/// @param leontief_inverse_matrix A nxn matrix of Leontief inverse.
-/// @return A vector of power of dispersion coefficients of variation.
-/// @noRd
-fn compute_power_dispersion_cv(
+/// Coefficients of variation of the columns of the Leontief inverse.
+fn power_dispersion_cv(
   leontief_inverse_matrix: &[f64]
 ) -> Vec<f64> {
   
@@ -140,12 +132,8 @@ fn compute_power_dispersion_cv(
 
 }
 
-#[extendr]
-/// Computes sensitivity of dispersion coefficients of variation
-/// @param leontief_inverse_matrix A nxn matrix of Leontief or Ghosh inverse.
-/// @return A vector of sensitivity of dispersion coefficients of variation.
-/// @noRd
-fn compute_sensitivity_dispersion_cv(matrix: &[f64]) -> Vec<f64> {
+/// Coefficients of variation of the rows of the matrix.
+fn sensitivity_dispersion_cv(matrix: &[f64]) -> Vec<f64> {
   
   // get dimensions
   let n = (matrix.len() as f64).sqrt() as usize;
@@ -172,6 +160,308 @@ fn compute_sensitivity_dispersion_cv(matrix: &[f64]) -> Vec<f64> {
     .collect::<Vec<f64>>()
 }
 
+#[extendr]
+/// @description Computes sensitivity of dispersion
+/// @param matrix A nxn matrix of Leontief or Ghosh inverse.
+/// @param labels Optional labels of the sectors, attached as names.
+/// @return A vector of sensitivity of dispersion.
+/// @noRd
+fn compute_sensitivity_dispersion(
+  matrix: &[f64],
+  #[default = "NULL"] labels: Nullable<Strings>
+) -> Result<Doubles> {
+  set_names(sensitivity_dispersion(matrix), &labels)
+}
+
+#[extendr]
+/// Computes power of dispersion
+/// @param leontief_inverse_matrix A nxn matrix of Leontief inverse.
+/// @param labels Optional labels of the sectors, attached as names.
+/// @return A vector of power of dispersion.
+/// @noRd
+fn compute_power_dispersion(
+  leontief_inverse_matrix: &[f64],
+  #[default = "NULL"] labels: Nullable<Strings>
+) -> Result<Doubles> {
+  set_names(power_dispersion(leontief_inverse_matrix), &labels)
+}
+
+#[extendr]
+/// Computes power of dispersion coefficients of variation
+/// @param leontief_inverse_matrix A nxn matrix of Leontief inverse.
+/// @param labels Optional labels of the sectors, attached as names.
+/// @return A vector of power of dispersion coefficients of variation.
+/// @noRd
+fn compute_power_dispersion_cv(
+  leontief_inverse_matrix: &[f64],
+  #[default = "NULL"] labels: Nullable<Strings>
+) -> Result<Doubles> {
+  set_names(power_dispersion_cv(leontief_inverse_matrix), &labels)
+}
+
+#[extendr]
+/// Computes sensitivity of dispersion coefficients of variation
+/// @param leontief_inverse_matrix A nxn matrix of Leontief or Ghosh inverse.
+/// @param labels Optional labels of the sectors, attached as names.
+/// @return A vector of sensitivity of dispersion coefficients of variation.
+/// @noRd
+fn compute_sensitivity_dispersion_cv(
+  matrix: &[f64],
+  #[default = "NULL"] labels: Nullable<Strings>
+) -> Result<Doubles> {
+  set_names(sensitivity_dispersion_cv(matrix), &labels)
+}
+
+#[extendr]
+/// Computes power and sensitivity of dispersion indices and their coefficients of variation
+/// @param leontief_factorization The factorization of the Leontief matrix, as returned by `compute_factorization()`.
+/// @param forward_factorization The factorization of the base matrix (Leontief or Ghosh) used for forward linkages.
+/// @param threads Number of threads. 0 means the session-wide setting.
+/// @param labels Optional labels of the sectors, attached as row names.
+/// @details Inverses are read from the cached factorizations, without copying them from R.
+/// @return A nx4 matrix of power of dispersion, sensitivity of dispersion and their coefficients of variation.
+/// @noRd
+fn compute_dispersion_indices(
+  leontief_factorization: ExternalPtr<Factorization>,
+  forward_factorization: ExternalPtr<Factorization>,
+  threads: usize,
+  #[default = "NULL"] labels: Nullable<Strings>
+) -> Result<RArray<f64, [usize;2]>> {
+
+  // get dimensions
+  let n = leontief_factorization.n();
+
+  let leontief_factorization: &Factorization = &leontief_factorization;
+  let forward_factorization: &Factorization = &forward_factorization;
+  let indices = with_threads(threads, || {
+    // get inverses in column-major order
+    let leontief_inverse_matrix = leontief_factorization.inverse_col_major();
+    let forward_matrix = forward_factorization.inverse_col_major();
+    [
+      power_dispersion(&leontief_inverse_matrix),
+      sensitivity_dispersion(&forward_matrix),
+      power_dispersion_cv(&leontief_inverse_matrix),
+      sensitivity_dispersion_cv(&forward_matrix)
+    ]
+  })?;
+
+  set_dimnames(
+    RArray::new_matrix(n, 4, |row, col| indices[col][row]),
+    &labels,
+    &fixed_labels(&["power_dispersion", "sensitivity_dispersion", "power_dispersion_cv", "sensitivity_dispersion_cv"])
+  )
+}
+
+/// Ratio of a value to a total, zero when the total is zero.
+fn ratio(value: f64, total: f64) -> f64 {
+  if total == 0.0 { 0.0 } else { value / total }
+}
+
+/// Indices over their average within groups of `group_size` consecutive elements, or over all elements if
+/// `group_size` is the length of `values`. Indices are zero in groups that average zero.
+fn normalize_by_group(values: &[f64], group_size: usize) -> Vec<f64> {
+  values
+    .chunks(group_size)
+    .flat_map(|group| {
+      let average = group.iter().sum::<f64>() / group.len() as f64;
+      group.iter().map(move |&value| ratio(value, average))
+    })
+    .collect()
+}
+
+#[extendr]
+/// Computes region-aware linkages
+/// @param leontief_factorization The factorization of the Leontief matrix, as returned by `compute_factorization()`.
+/// @param forward_factorization The factorization of the base matrix (Leontief or Ghosh) used for forward linkages.
+/// @param n_countries Number of countries.
+/// @param normalization Average that indices are relative to, one of "country", "global" or "domestic".
+/// @param threads Number of threads. 0 means the session-wide setting.
+/// @param labels Optional labels of the country-sectors, attached as row names.
+/// @details Sectors are ordered by country. Backward linkages (column sums of the Leontief inverse) are split into
+/// output induced in the country of the sector (domestic) and abroad (international), and forward linkages (row sums
+/// of the forward inverse) into sales to the country of the sector and abroad. Power and sensitivity of dispersion
+/// are total linkages over their average across the sectors of the same country ("country") or of all countries
+/// ("global"), or domestic linkages over their average across the sectors of the same country ("domestic"), the
+/// indices of each country's diagonal block on its own. Indices are zero where their average is zero.
+/// @return A nx6 matrix of domestic and international backward and forward linkages and power and sensitivity of
+/// dispersion.
+/// @noRd
+fn compute_regional_linkages(
+  leontief_factorization: ExternalPtr<Factorization>,
+  forward_factorization: ExternalPtr<Factorization>,
+  n_countries: usize,
+  normalization: &str,
+  threads: usize,
+  #[default = "NULL"] labels: Nullable<Strings>
+) -> Result<RArray<f64, [usize;2]>> {
+
+  // get dimensions
+  let n = leontief_factorization.n();
+  if n_countries == 0 || n % n_countries != 0 {
+    return Err("Number of sectors must be a multiple of the number of countries.".into());
+  }
+  if forward_factorization.n() != n {
+    return Err("Leontief and forward matrices must have the same dimensions.".into());
+  }
+  let n_sectors = n / n_countries;
+  let leontief_inverse = leontief_factorization.inverse_in_pool(threads)?;
+  let forward_inverse = forward_factorization.inverse_in_pool(threads)?;
+
+  // domestic and total linkages, by column (backward) and by row (forward)
+  let (backward, forward) = with_threads(threads, || {
+    let backward: Vec<(f64, f64)> = leontief_inverse
+      .par_col_iter()
+      .enumerate()
+      .map(|(j, col)| {
+        let offset = j / n_sectors * n_sectors;
+        ((offset..offset + n_sectors).map(|i| col[i]).sum::<f64>(), col.iter().sum::<f64>())
+      })
+      .collect();
+    let forward: Vec<(f64, f64)> = forward_inverse
+      .par_row_iter()
+      .enumerate()
+      .map(|(i, row)| {
+        let offset = i / n_sectors * n_sectors;
+        ((offset..offset + n_sectors).map(|j| row[j]).sum::<f64>(), row.iter().sum::<f64>())
+      })
+      .collect();
+    (backward, forward)
+  })?;
+
+  // power and sensitivity of dispersion, by normalization
+  let group_size = match normalization {
+    "country" | "domestic" => n_sectors,
+    "global" => n,
+    _ => return Err("normalization must be one of \"country\", \"global\" or \"domestic\".".into()),
+  };
+  let linkage = |(domestic, total): &(f64, f64)| if normalization == "domestic" { *domestic } else { *total };
+  let power = normalize_by_group(&backward.iter().map(linkage).collect::<Vec<f64>>(), group_size);
+  let sensitivity = normalize_by_group(&forward.iter().map(linkage).collect::<Vec<f64>>(), group_size);
+
+  set_dimnames(
+    RArray::new_matrix(n, 6, |row, col| match col {
+      0 => backward[row].0,
+      1 => backward[row].1 - backward[row].0,
+      2 => forward[row].0,
+      3 => forward[row].1 - forward[row].0,
+      4 => power[row],
+      _ => sensitivity[row],
+    }),
+    &labels,
+    &fixed_labels(&[
+      "backward_domestic",
+      "backward_international",
+      "forward_domestic",
+      "forward_international",
+      "power_dispersion",
+      "sensitivity_dispersion"
+    ])
+  )
+}
+
+/// Key sector type of each sector, by whether its backward and forward indices are above one.
+fn classify_key_sectors(backward: &[f64], forward: &[f64]) -> Strings {
+  Strings::from_values(backward.iter().zip(forward).map(|(&backward, &forward)| {
+    match (backward > 1.0, forward > 1.0) {
+      (true, true) => "Key Sector",
+      (true, false) => "Strong Backward Linkage",
+      (false, true) => "Strong Forward Linkage",
+      (false, false) => "Non-Key Sector",
+    }
+  }))
+}
+
+#[extendr]
+/// Computes weighted linkage indices
+/// @param leontief_factorization The factorization of the Leontief matrix, as returned by `compute_factorization()`.
+/// @param forward_factorization The factorization of the base matrix (Leontief or Ghosh) used for forward linkages.
+/// @param final_demand A 1xn vector of final demand.
+/// @param value_added A 1xn vector of value added.
+/// @param threads Number of threads. 0 means the session-wide setting.
+/// @param labels Optional labels of the sectors, attached as row names.
+/// @details With backward linkages \eqn{m_j} (column sums of the Leontief inverse), forward linkages \eqn{g_i} (row
+/// sums of the forward inverse), final demand shares \eqn{w_j} and value-added shares \eqn{v_i}, weighted indices
+/// are linkages over their weighted average, \eqn{m_j / \sum_k w_k m_k} and \eqn{g_i / \sum_k v_k g_k}
+/// \insertCite{cuello_identification_1992}{fio}. Laumas indices weight the linkages themselves,
+/// \eqn{w_j m_j} and \eqn{v_i g_i}, over their simple average \insertCite{laumas_weighting_1976}{fio}. The net
+/// backward linkage, \eqn{m_j f_j / x_j} with \eqn{x = L f}, is output generated by the final demand of a sector
+/// per unit of its own output, normalised by its output-weighted average \insertCite{dietzenbacher_more_2005}{fio}.
+/// Sectors are classified as "Key Sector", "Strong Backward Linkage", "Strong Forward Linkage" or "Non-Key Sector" by
+/// whether their backward and forward indices are above one. Indices are zero when their total or average is zero.
+/// @return A list with a nx5 matrix of weighted and Laumas backward and forward indices and normalised net backward
+/// linkages, and key sector types by weighted and by Laumas indices.
+/// @references \insertAllCited{}
+/// @noRd
+fn compute_weighted_linkages(
+  leontief_factorization: ExternalPtr<Factorization>,
+  forward_factorization: ExternalPtr<Factorization>,
+  final_demand: &[f64],
+  value_added: &[f64],
+  threads: usize,
+  #[default = "NULL"] labels: Nullable<Strings>
+) -> Result<List> {
+
+  // get dimensions
+  let n = leontief_factorization.n();
+  if forward_factorization.n() != n {
+    return Err("Leontief and forward matrices must have the same dimensions.".into());
+  }
+  if final_demand.len() != n || value_added.len() != n {
+    return Err("final_demand and value_added must have one element for each sector.".into());
+  }
+
+  // final demand and value-added shares
+  let total_final_demand = final_demand.iter().sum::<f64>();
+  let total_value_added = value_added.iter().sum::<f64>();
+  let demand_shares: Vec<f64> = final_demand.iter().map(|&value| ratio(value, total_final_demand)).collect();
+  let supply_shares: Vec<f64> = value_added.iter().map(|&value| ratio(value, total_value_added)).collect();
+
+  // backward and forward linkages and output
+  let leontief_factorization: &Factorization = &leontief_factorization;
+  let forward_factorization: &Factorization = &forward_factorization;
+  let (backward, forward, output) = with_threads(threads, || {
+    let backward: Vec<f64> =
+      leontief_factorization.inverse().par_col_iter().map(|col| col.iter().sum::<f64>()).collect();
+    let forward: Vec<f64> =
+      forward_factorization.inverse().par_row_iter().map(|row| row.iter().sum::<f64>()).collect();
+    let output = leontief_factorization.solve(final_demand, false);
+    (backward, forward, output)
+  })?;
+
+  // weighted and laumas indices
+  let weighted_average = |linkages: &[f64], weights: &[f64]| {
+    linkages.iter().zip(weights).map(|(linkage, weight)| linkage * weight).sum::<f64>()
+  };
+  let backward_average = weighted_average(&backward, &demand_shares);
+  let forward_average = weighted_average(&forward, &supply_shares);
+  let backward_weighted: Vec<f64> = backward.iter().map(|&value| ratio(value, backward_average)).collect();
+  let forward_weighted: Vec<f64> = forward.iter().map(|&value| ratio(value, forward_average)).collect();
+  let backward_laumas: Vec<f64> = (0..n).map(|j| n as f64 * demand_shares[j] * backward_weighted[j]).collect();
+  let forward_laumas: Vec<f64> = (0..n).map(|i| n as f64 * supply_shares[i] * forward_weighted[i]).collect();
+
+  // net backward linkages, normalised by their output-weighted average
+  let net_backward: Vec<f64> = (0..n)
+    .map(|j| ratio(backward[j] * final_demand[j], output[j]))
+    .collect();
+  let net_backward_average = ratio(weighted_average(&net_backward, &output), output.iter().sum::<f64>());
+
+  Ok(list!(
+    indices = set_dimnames(
+      RArray::new_matrix(n, 5, |row, col| match col {
+        0 => backward_weighted[row],
+        1 => forward_weighted[row],
+        2 => backward_laumas[row],
+        3 => forward_laumas[row],
+        _ => ratio(net_backward[row], net_backward_average),
+      }),
+      &labels,
+      &fixed_labels(&["backward_weighted", "forward_weighted", "backward_laumas", "forward_laumas", "net_backward"])
+    )?,
+    key_sectors = classify_key_sectors(&backward_weighted, &forward_weighted),
+    laumas_key_sectors = classify_key_sectors(&backward_laumas, &forward_laumas)
+  ))
+}
+
 // Macro to generate exports.
 // This ensures exported functions are registered with R.
 // See corresponding C code in `entrypoint.c`.
@@ -181,4 +471,7 @@ extendr_module! {
   fn compute_sensitivity_dispersion_cv;
   fn compute_power_dispersion;
   fn compute_sensitivity_dispersion;
+  fn compute_dispersion_indices;
+  fn compute_regional_linkages;
+  fn compute_weighted_linkages;
 }
\ No newline at end of file
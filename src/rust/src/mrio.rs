@@ -0,0 +1,1150 @@
+use extendr_api::prelude::*;
+use faer::{linalg::solvers::Solve, Mat};
+use rayon::prelude::*;
+
+use crate::factorization::Factorization;
+use crate::labels::{fixed_labels, set_dimnames, set_dimnames_3d, set_list_names, set_names};
+use crate::parallel::with_threads;
+use crate::satellite::satellite_requirements;
+
+// number of terms of the wang-wei-zhu decomposition of gross exports
+const WWZ_TERMS: usize = 16;
+
+// names of the wang-wei-zhu terms
+const WWZ_TERM_NAMES: [&str; WWZ_TERMS] = [
+  "dva_fin", "dva_int", "dva_intrex_i1", "dva_intrex_f", "dva_intrex_i2",
+  "rdv_fin1", "rdv_fin2", "rdv_int", "ddc_fin", "ddc_int",
+  "mva_fin", "mva_int", "mdc", "ova_fin", "ova_int", "odc",
+];
+
+// names of the production-based terms of gvc participation
+const GVC_TERM_NAMES: [&str; 4] = ["pure_domestic", "traditional_trade", "simple_gvc", "complex_gvc"];
+
+/// Value added per unit of output, zero for sectors without output.
+fn value_added_coefficients(value_added: &[f64], total_production: &[f64]) -> Vec<f64> {
+  value_added
+    .iter()
+    .zip(total_production)
+    .map(|(&va, &x)| if x == 0.0 { 0.0 } else { va / x })
+    .collect()
+}
+
+/// Bilateral flows from a producing country, as a \eqn{s x r} matrix of intermediate and final exports,
+/// \eqn{A_{pc} x_c + y_{pc}}, by sector of the producer and consuming country. The column of the producer itself
+/// holds its domestic final demand, \eqn{y_{pp}}.
+fn bilateral_exports(
+  tech_coeff: &Mat<f64>,
+  total_production: &[f64],
+  final_demand: &Mat<f64>,
+  n_sectors: usize,
+  producer: usize
+) -> Mat<f64> {
+  let offset = producer * n_sectors;
+
+  Mat::from_fn(n_sectors, final_demand.ncols(), |i, consumer| {
+    let final_exports = final_demand[(offset + i, consumer)];
+    if consumer == producer {
+      return final_exports;
+    }
+    let intermediate_exports: f64 = (consumer * n_sectors..(consumer + 1) * n_sectors)
+      .map(|j| tech_coeff[(offset + i, j)] * total_production[j])
+      .sum();
+    intermediate_exports + final_exports
+  })
+}
+
+/// Bilateral flows from every country, as returned by `bilateral_exports()`.
+fn bilateral_exports_all(
+  tech_coeff: &Mat<f64>,
+  total_production: &[f64],
+  final_demand: &Mat<f64>,
+  n_sectors: usize
+) -> Vec<Mat<f64>> {
+  (0..final_demand.ncols())
+    .into_par_iter()
+    .map(|producer| bilateral_exports(tech_coeff, total_production, final_demand, n_sectors, producer))
+    .collect()
+}
+
+/// Gross exports by sector, \eqn{E^{s*}}, from bilateral flows of every country.
+fn gross_exports(flows: &[Mat<f64>], n_sectors: usize) -> Vec<f64> {
+  (0..flows.len() * n_sectors)
+    .map(|row| {
+      let producer = row / n_sectors;
+      (0..flows.len())
+        .filter(|&consumer| consumer != producer)
+        .map(|consumer| flows[producer][(row % n_sectors, consumer)])
+        .sum()
+    })
+    .collect()
+}
+
+/// Local Leontief inverses of each country, \eqn{L^{ss} = (I - A^{ss})^{-1}}.
+fn local_leontief_inverses(tech_coeff: &Mat<f64>, n_countries: usize) -> Vec<Mat<f64>> {
+  let n_sectors = tech_coeff.nrows() / n_countries;
+  let identity_matrix: Mat<f64> = Mat::identity(n_sectors, n_sectors);
+
+  (0..n_countries)
+    .into_par_iter()
+    .map(|country| {
+      let offset = country * n_sectors;
+      let local_matrix = &identity_matrix - tech_coeff.submatrix(offset, offset, n_sectors, n_sectors);
+      local_matrix.partial_piv_lu().solve(&identity_matrix)
+    })
+    .collect()
+}
+
+/// Value added of each origin country embodied in a unit of output of each country-sector, \eqn{\hat{V} B}, as a
+/// \eqn{r x n} matrix.
+fn value_added_by_origin(va_coeff: &[f64], leontief_inverse: &Mat<f64>, n_countries: usize) -> Mat<f64> {
+  let n_sectors = va_coeff.len() / n_countries;
+  let value_added_coeff = Mat::from_fn(n_countries, va_coeff.len(), |origin, col| {
+    if col / n_sectors == origin {
+      va_coeff[col]
+    } else {
+      0.0
+    }
+  });
+
+  value_added_coeff * leontief_inverse
+}
+
+/// Sums of the Leontief inverse over the sectors of each country, for each column, as a \eqn{r x n} matrix. These
+/// are the output multipliers of a unit final demand shock in each country-sector, by country where output is
+/// induced.
+fn country_column_sums(leontief_inverse: &Mat<f64>, n_countries: usize) -> Mat<f64> {
+  let n_sectors = leontief_inverse.nrows() / n_countries;
+
+  let columns: Vec<Vec<f64>> = leontief_inverse
+    .par_col_iter()
+    .map(|col| {
+      (0..n_countries)
+        .map(|country| (country * n_sectors..(country + 1) * n_sectors).map(|i| col[i]).sum())
+        .collect()
+    })
+    .collect();
+
+  Mat::from_fn(n_countries, leontief_inverse.ncols(), |country, col| columns[col][country])
+}
+
+/// Sums of the columns of a \eqn{r x n} matrix over the sectors of each country, as a \eqn{r x r} matrix.
+fn country_block_sums(by_country: &Mat<f64>, n_countries: usize) -> Mat<f64> {
+  let n_sectors = by_country.ncols() / n_countries;
+
+  Mat::from_fn(by_country.nrows(), n_countries, |row, country| {
+    (country * n_sectors..(country + 1) * n_sectors).map(|col| by_country[(row, col)]).sum::<f64>()
+  })
+}
+
+/// Round decomposition of a multi-regional Leontief inverse, \eqn{L = M_3 M_2 M_1}, as \eqn{r x r} block sums of
+/// intra-regional (\eqn{M_1}), spillover (\eqn{(M_2 - I) M_1}) and feedback (\eqn{(M_3 - I) M_2 M_1}) effects, and
+/// \eqn{r x n} sums of \eqn{M_1} over the sectors of each country.
+///
+/// Only block sums are needed, so powers of \eqn{A^* = M_1 A_o} are applied to the \eqn{r x n} country aggregation
+/// matrix instead of being formed.
+fn regional_decomposition(
+  tech_coeff: &Mat<f64>,
+  leontief_inverse: &Mat<f64>,
+  n_countries: usize
+) -> (Mat<f64>, Mat<f64>, Mat<f64>, Mat<f64>) {
+  let n = tech_coeff.nrows();
+  let n_sectors = n / n_countries;
+  let country = |i: usize| i / n_sectors;
+
+  // intra-regional inverses, M_1 = (I - A_d)^{-1}, and off-diagonal blocks of technical coefficients, A_o
+  let local_leontief = local_leontief_inverses(tech_coeff, n_countries);
+  let foreign_coeff = Mat::from_fn(n, n, |i, j| {
+    if country(i) == country(j) {
+      0.0
+    } else {
+      tech_coeff[(i, j)]
+    }
+  });
+
+  // block-diagonal product with intra-regional inverses, P M_1
+  let local_right = |m: &Mat<f64>| {
+    Mat::from_fn(m.nrows(), n, |row, j| {
+      let offset = country(j) * n_sectors;
+      (0..n_sectors).map(|k| m[(row, offset + k)] * local_leontief[country(j)][(k, j - offset)]).sum::<f64>()
+    })
+  };
+
+  // country aggregation of M_1 and of M_2 M_1 = (I + A^* + ... + A^{*(r - 1)}) M_1, since E' A^* = (E' M_1) A_o
+  let aggregation = Mat::from_fn(n_countries, n, |row, i| if country(i) == row { 1.0 } else { 0.0 });
+  let intra_regional = local_right(&aggregation);
+  let mut power = intra_regional.clone();
+  let mut intra_and_spillover = intra_regional.clone();
+  for _ in 1..n_countries {
+    power = local_right(&(&power * &foreign_coeff));
+    intra_and_spillover += &power;
+  }
+
+  // block sums of each effect, feedback closing the gap to the leontief inverse
+  let local = country_block_sums(&intra_regional, n_countries);
+  let partial = country_block_sums(&intra_and_spillover, n_countries);
+  let total = country_block_sums(&country_column_sums(leontief_inverse, n_countries), n_countries);
+
+  let spillover = &partial - &local;
+  let feedback = &total - &partial;
+
+  (local, spillover, feedback, intra_regional)
+}
+
+#[allow(clippy::too_many_arguments)]
+#[extendr]
+/// @description
+/// Computes stressors embodied in bilateral trade of a multi-regional input-output model, by the MRIO and EEBT
+/// methods.
+///
+/// @param satellite
+/// A \eqn{k x n} matrix of stressors, one row for each stressor.
+/// @param total_production
+/// A \eqn{1 x n} vector of total production.
+/// @param tech_coeff
+/// A \eqn{n x n} matrix of technical coefficients.
+/// @param leontief_factorization
+/// The factorization of the Leontief matrix, as returned by `compute_factorization()`.
+/// @param final_demand
+/// A \eqn{n x r} matrix of final demand, one column for each consuming country.
+/// @param n_countries
+/// Number of countries.
+/// @param threads
+/// Number of threads. 0 means the session-wide setting.
+/// @param indicator_labels
+/// Optional labels of the stressors, attached as names of the first dimension.
+/// @param countries
+/// Optional labels of the countries, attached as names of the producing and consuming countries.
+///
+/// @details
+/// Sectors are ordered by country, so that the block \eqn{(p, c)} of a matrix refers to sectors of countries
+/// \eqn{p} and \eqn{c}. In the MRIO method, stressors generated in country \eqn{p} to satisfy the final demand of
+/// country \eqn{c} are traced along the whole multi-regional supply chain
+/// \insertCite{miller_input-output_2009}{fio}:
+///
+/// \deqn{Q^{mrio}_{pc} = r_p (L y_c)_p}
+///
+/// In the EEBT (emissions embodied in bilateral trade) method, exports of country \eqn{p} to country \eqn{c},
+/// intermediate and final, are valued with domestic multipliers of \eqn{p}:
+///
+/// \deqn{Q^{eebt}_{pc} = r_p (I - A_{pp})^{-1} (A_{pc} x_c + y_{pc})}
+///
+/// for \eqn{c \neq p}, while \eqn{Q^{eebt}_{pp} = r_p (I - A_{pp})^{-1} y_{pp}}.
+///
+/// In both cases, row sums are production-based accounts and column sums are consumption-based accounts.
+///
+/// @return
+/// A list with \eqn{k x r x r} arrays of stressors embodied in bilateral flows, from producing (rows) to consuming
+/// (columns) countries, by the MRIO and EEBT methods.
+///
+/// @references
+/// \insertAllCited{}
+///
+/// @noRd
+fn compute_embodied_trade(
+  satellite: &[f64],
+  total_production: &[f64],
+  tech_coeff: &[f64],
+  leontief_factorization: ExternalPtr<Factorization>,
+  final_demand: RMatrix<f64>,
+  n_countries: usize,
+  threads: usize,
+  #[default = "NULL"] indicator_labels: Nullable<Strings>,
+  #[default = "NULL"] countries: Nullable<Strings>
+) -> Result<List> {
+
+  // get dimensions
+  let n = leontief_factorization.n();
+  if n_countries == 0 || n % n_countries != 0 {
+    return Err("Number of sectors must be a multiple of the number of countries.".into());
+  }
+  if total_production.len() != n || satellite.len() % n != 0 {
+    return Err("satellite must have one column for each sector.".into());
+  }
+  if final_demand.nrows() != n || final_demand.ncols() != n_countries {
+    return Err("final_demand must have one row for each sector and one column for each country.".into());
+  }
+  let n_sectors = n / n_countries;
+  let k = satellite.len() / n;
+  let leontief_inverse = leontief_factorization.inverse_in_pool(threads)?;
+
+  // create faer matrices
+  let tech_coeff_matrix = Mat::from_fn(n, n, |row, col| tech_coeff[col * n + row]);
+  let final_demand_matrix = Mat::from_fn(n, n_countries, |row, col| final_demand[[row, col]]);
+
+  // stressor intensities
+  let requirements = satellite_requirements(satellite, total_production);
+
+  let (mrio, eebt) = with_threads(threads, || {
+    // output required by final demand of each country, L y_c
+    let output = leontief_inverse * &final_demand_matrix;
+
+    // stressors by producing and consuming country, r_p (L y_c)_p
+    let mrio: Vec<Mat<f64>> = (0..n_countries)
+      .into_par_iter()
+      .map(|producer| {
+        let sectors = producer * n_sectors..(producer + 1) * n_sectors;
+        Mat::from_fn(k, n_countries, |h, consumer| {
+          sectors.clone().map(|i| requirements[(h, i)] * output[(i, consumer)]).sum()
+        })
+      })
+      .collect();
+
+    // stressors embodied in bilateral exports valued with domestic multipliers
+    let eebt: Vec<Mat<f64>> = (0..n_countries)
+      .into_par_iter()
+      .map(|producer| {
+        let offset = producer * n_sectors;
+
+        // domestic multipliers, r_p (I - A_pp)^{-1}
+        let identity_matrix: Mat<f64> = Mat::identity(n_sectors, n_sectors);
+        let domestic_leontief = &identity_matrix - tech_coeff_matrix.submatrix(offset, offset, n_sectors, n_sectors);
+        let domestic_requirements = requirements.submatrix(0, offset, k, n_sectors).transpose().to_owned();
+        let multipliers = domestic_leontief.transpose().partial_piv_lu().solve(domestic_requirements);
+
+        // exports from producer to each consumer, intermediate and final
+        let exports = bilateral_exports(
+          &tech_coeff_matrix,
+          total_production,
+          &final_demand_matrix,
+          n_sectors,
+          producer
+        );
+
+        multipliers.transpose() * exports
+      })
+      .collect();
+
+    (mrio, eebt)
+  })?;
+
+  let dimnames = [&indicator_labels, &countries, &countries];
+  Ok(list!(
+    mrio = set_dimnames_3d(
+      RArray::new_matrix3d(k, n_countries, n_countries, |h, producer, consumer| mrio[producer][(h, consumer)]),
+      dimnames
+    )?,
+    eebt = set_dimnames_3d(
+      RArray::new_matrix3d(k, n_countries, n_countries, |h, producer, consumer| eebt[producer][(h, consumer)]),
+      dimnames
+    )?
+  ))
+}
+
+#[allow(clippy::too_many_arguments)]
+#[extendr]
+/// @description
+/// Computes trade in value added (TiVA) indicators of a multi-regional input-output model.
+///
+/// @param value_added
+/// A \eqn{1 x n} vector of value added.
+/// @param total_production
+/// A \eqn{1 x n} vector of total production.
+/// @param tech_coeff
+/// A \eqn{n x n} matrix of technical coefficients.
+/// @param leontief_factorization
+/// The factorization of the Leontief matrix, as returned by `compute_factorization()`.
+/// @param final_demand
+/// A \eqn{n x r} matrix of final demand, one column for each consuming country.
+/// @param n_countries
+/// Number of countries.
+/// @param threads
+/// Number of threads. 0 means the session-wide setting.
+/// @param labels
+/// Optional labels of the country-sectors, attached as names.
+/// @param countries
+/// Optional labels of the countries, attached as names.
+///
+/// @details
+/// Value-added coefficients, \eqn{v = va \hat{x}^{-1}} (zero for sectors without output), times the Leontief inverse
+/// give the value added of each origin country embodied in a unit of output of each country-sector,
+/// \eqn{W = \hat{V} L}, where \eqn{\hat{V}} is a \eqn{r x n} matrix holding the coefficients of country \eqn{o} in
+/// row \eqn{o} \insertCite{miller_input-output_2009}{fio}. Gross exports of sector \eqn{j} of country \eqn{p},
+/// \eqn{e_j = \sum_{c \neq p} (A_{pc} x_c + y_{pc})_j}, split into domestic value added, \eqn{W_{pj} e_j}, and
+/// foreign value added, \eqn{\sum_{o \neq p} W_{oj} e_j}. Value added of origin \eqn{o} absorbed by the final
+/// demand of destination \eqn{c} is \eqn{W Y}.
+///
+/// @return
+/// A list with vectors of gross exports, domestic and foreign value added in exports by country-sector, a \eqn{r x r}
+/// matrix of value added in exports by origin (rows) and exporting country (columns), and a \eqn{r x r} matrix of
+/// value added in final demand by origin (rows) and destination (columns).
+///
+/// @references
+/// \insertAllCited{}
+///
+/// @noRd
+fn compute_tiva(
+  value_added: &[f64],
+  total_production: &[f64],
+  tech_coeff: &[f64],
+  leontief_factorization: ExternalPtr<Factorization>,
+  final_demand: RMatrix<f64>,
+  n_countries: usize,
+  threads: usize,
+  #[default = "NULL"] labels: Nullable<Strings>,
+  #[default = "NULL"] countries: Nullable<Strings>
+) -> Result<List> {
+
+  // get dimensions
+  let n = leontief_factorization.n();
+  if n_countries == 0 || n % n_countries != 0 {
+    return Err("Number of sectors must be a multiple of the number of countries.".into());
+  }
+  if total_production.len() != n || value_added.len() != n {
+    return Err("value_added must have one element for each sector.".into());
+  }
+  if final_demand.nrows() != n || final_demand.ncols() != n_countries {
+    return Err("final_demand must have one row for each sector and one column for each country.".into());
+  }
+  let n_sectors = n / n_countries;
+  let leontief_inverse = leontief_factorization.inverse_in_pool(threads)?;
+
+  // create faer matrices
+  let tech_coeff_matrix = Mat::from_fn(n, n, |row, col| tech_coeff[col * n + row]);
+  let final_demand_matrix = Mat::from_fn(n, n_countries, |row, col| final_demand[[row, col]]);
+
+  // value-added coefficients
+  let va_coeff = value_added_coefficients(value_added, total_production);
+
+  let (embodied, final_demand_va, exports) = with_threads(threads, || {
+    // value added by origin embodied in a unit of output, V L
+    let embodied = value_added_by_origin(&va_coeff, leontief_inverse, n_countries);
+
+    // value added by origin absorbed in final demand of each destination, V L Y
+    let final_demand_va = &embodied * &final_demand_matrix;
+
+    // gross exports by sector
+    let flows = bilateral_exports_all(&tech_coeff_matrix, total_production, &final_demand_matrix, n_sectors);
+    let exports = gross_exports(&flows, n_sectors);
+
+    (embodied, final_demand_va, exports)
+  })?;
+
+  // value added in exports by origin and exporting country
+  let exports_va = Mat::from_fn(n_countries, n_countries, |origin, exporter| {
+    (exporter * n_sectors..(exporter + 1) * n_sectors)
+      .map(|j| embodied[(origin, j)] * exports[j])
+      .sum::<f64>()
+  });
+
+  // domestic and foreign value added in exports by sector
+  let domestic_va: Vec<f64> = (0..n).map(|j| embodied[(j / n_sectors, j)] * exports[j]).collect();
+  let foreign_va: Vec<f64> = (0..n)
+    .map(|j| {
+      (0..n_countries)
+        .filter(|&origin| origin != j / n_sectors)
+        .map(|origin| embodied[(origin, j)] * exports[j])
+        .sum()
+    })
+    .collect();
+
+  Ok(list!(
+    exports = set_names(exports, &labels)?,
+    domestic_value_added = set_names(domestic_va, &labels)?,
+    foreign_value_added = set_names(foreign_va, &labels)?,
+    value_added_exports = set_dimnames(
+      RArray::new_matrix(n_countries, n_countries, |row, col| exports_va[(row, col)]),
+      &countries,
+      &countries
+    )?,
+    value_added_final_demand = set_dimnames(
+      RArray::new_matrix(n_countries, n_countries, |row, col| final_demand_va[(row, col)]),
+      &countries,
+      &countries
+    )?
+  ))
+}
+
+/// Gross exports decomposed into the 16 terms of Wang, Wei and Zhu (2013).
+///
+/// Returns a matrix with one row for each exporter, importer and sector of the exporter (in this order of nesting)
+/// and one column for each term, bilateral exports in the same row order, and local Leontief inverses by country.
+fn wwz_decomposition(
+  tech_coeff: &Mat<f64>,
+  total_production: &[f64],
+  leontief_inverse: &Mat<f64>,
+  final_demand: &Mat<f64>,
+  value_added: &[f64],
+  n_countries: usize
+) -> (Mat<f64>, Vec<f64>, Vec<Mat<f64>>) {
+  let n = total_production.len();
+  let n_sectors = n / n_countries;
+
+  // value-added coefficients
+  let va_coeff = value_added_coefficients(value_added, total_production);
+
+  // local leontief inverses, L^{ss}
+  let local_leontief = local_leontief_inverses(tech_coeff, n_countries);
+
+  // value added of each origin embodied in output of each country-sector, V^t B^{ts}
+  let global_va = value_added_by_origin(&va_coeff, leontief_inverse, n_countries);
+
+  // domestic value added in local production, V^s L^{ss}
+  let local_va = Mat::from_fn(n_countries, n_sectors, |country, col| {
+    (0..n_sectors)
+      .map(|i| va_coeff[country * n_sectors + i] * local_leontief[country][(i, col)])
+      .sum::<f64>()
+  });
+
+  // bilateral and total exports, E^{sr} and E^{s*}
+  let flows = bilateral_exports_all(tech_coeff, total_production, final_demand, n_sectors);
+  let total_exports = gross_exports(&flows, n_sectors);
+
+  // terms of exports from each exporter to importer r
+  let terms_by_importer: Vec<Vec<Mat<f64>>> = (0..n_countries)
+    .into_par_iter()
+    .map(|importer| {
+      let r = importer * n_sectors;
+
+      // output of r required by final demand of each country produced by each country, B^{rt} Y^{tu}
+      let output: Vec<Mat<f64>> = (0..n_countries)
+        .map(|t| {
+          leontief_inverse.submatrix(r, t * n_sectors, n_sectors, n_sectors)
+            * final_demand.submatrix(t * n_sectors, 0, n_sectors, n_countries)
+        })
+        .collect();
+
+      // local production of r for its own final demand and for exports, L^{rr} Y^{rr} and L^{rr} E^{r*}
+      let local_demand = Mat::from_fn(n_sectors, 2, |row, col| match col {
+        0 => final_demand[(r + row, importer)],
+        _ => total_exports[r + row],
+      });
+      let local_output = &local_leontief[importer] * local_demand;
+
+      (0..n_countries)
+        .map(|exporter| {
+          if exporter == importer {
+            return Mat::zeros(0, 0);
+          }
+          let s = exporter * n_sectors;
+          let third = || (0..n_countries).filter(|&t| t != exporter && t != importer);
+
+          // destinations of exports of intermediates from s to r
+          let destinations = Mat::from_fn(n_sectors, 11, |row, col| match col {
+            0 => output[importer][(row, importer)],
+            1 => third().map(|t| output[t][(row, t)]).sum(),
+            2 => third().map(|t| output[importer][(row, t)]).sum(),
+            3 => third()
+              .map(|t| (0..n_countries).filter(|&u| u != exporter && u != t).map(|u| output[t][(row, u)]).sum::<f64>())
+              .sum(),
+            4 => output[importer][(row, exporter)],
+            5 => third().map(|t| output[t][(row, exporter)]).sum(),
+            6 => output[exporter][(row, exporter)],
+            7 => (0..n_countries).filter(|&u| u != exporter).map(|u| output[exporter][(row, u)]).sum(),
+            8 => total_production[r + row],
+            9 => local_output[(row, 0)],
+            _ => local_output[(row, 1)],
+          });
+          let intermediate = tech_coeff.submatrix(s, r, n_sectors, n_sectors) * destinations;
+
+          Mat::from_fn(n_sectors, WWZ_TERMS, |i, term| {
+            let domestic_global = global_va[(exporter, s + i)];
+            let domestic_local = local_va[(exporter, i)];
+            let foreign_direct = global_va[(importer, s + i)];
+            let foreign_other: f64 = third().map(|t| global_va[(t, s + i)]).sum();
+            let final_exports = final_demand[(s + i, importer)];
+            match term {
+              0 => domestic_global * final_exports,
+              1..=8 => domestic_local * intermediate[(i, term - 1)],
+              9 => (domestic_global - domestic_local) * intermediate[(i, 8)],
+              10 => foreign_direct * final_exports,
+              11 => foreign_direct * intermediate[(i, 9)],
+              12 => foreign_direct * intermediate[(i, 10)],
+              13 => foreign_other * final_exports,
+              14 => foreign_other * intermediate[(i, 9)],
+              _ => foreign_other * intermediate[(i, 10)],
+            }
+          })
+        })
+        .collect()
+    })
+    .collect();
+
+  // rows by exporter, importer and sector
+  let pairs = n_countries - 1;
+  let locate = |row: usize| {
+    let exporter = row / (pairs * n_sectors);
+    let importer = (row / n_sectors) % pairs;
+    let importer = if importer < exporter { importer } else { importer + 1 };
+    (exporter, importer, row % n_sectors)
+  };
+  let n_rows = n_countries * pairs * n_sectors;
+  let terms = Mat::from_fn(n_rows, WWZ_TERMS, |row, term| {
+    let (exporter, importer, i) = locate(row);
+    terms_by_importer[importer][exporter][(i, term)]
+  });
+  let exports: Vec<f64> = (0..n_rows)
+    .map(|row| {
+      let (exporter, importer, i) = locate(row);
+      flows[exporter][(i, importer)]
+    })
+    .collect();
+
+  (terms, exports, local_leontief)
+}
+
+#[allow(clippy::too_many_arguments)]
+#[extendr]
+/// @description
+/// Decomposes gross bilateral exports of a multi-regional input-output model into the 16 terms of Wang, Wei and
+/// Zhu.
+///
+/// @param value_added
+/// A \eqn{1 x n} vector of value added.
+/// @param total_production
+/// A \eqn{1 x n} vector of total production.
+/// @param tech_coeff
+/// A \eqn{n x n} matrix of technical coefficients.
+/// @param leontief_factorization
+/// The factorization of the Leontief matrix, as returned by `compute_factorization()`.
+/// @param final_demand
+/// A \eqn{n x r} matrix of final demand, one column for each consuming country.
+/// @param n_countries
+/// Number of countries.
+/// @param threads
+/// Number of threads. 0 means the session-wide setting.
+/// @param sectors
+/// Optional labels of the sectors of each country, attached as dimnames of the local Leontief inverses.
+/// @param countries
+/// Optional labels of the countries, attached as names of the list of local Leontief inverses.
+///
+/// @details
+/// Exports from country \eqn{s} to country \eqn{r}, \eqn{E^{sr} = A^{sr} x^r + y^{sr}}, are split by the global
+/// Leontief inverse \eqn{B} and the local Leontief inverses \eqn{L^{ss} = (I - A^{ss})^{-1}} into domestic value
+/// added absorbed abroad (terms 1 to 5), value added that returns home (6 to 8), domestic double counting (9 and 10),
+/// foreign value added (11, 12, 14 and 15) and foreign double counting (13 and 16)
+/// \insertCite{wang_quantifying_2013}{fio}. Terms sum up to gross exports by sector of the exporter.
+///
+/// @return
+/// A list with a matrix of the 16 terms, named after their abbreviations, with one row for each exporter, importer
+/// and sector of the exporter, the gross exports in the same rows, and the local Leontief inverses of each country.
+///
+/// @references
+/// \insertAllCited{}
+///
+/// @noRd
+fn compute_wwz_decomposition(
+  value_added: &[f64],
+  total_production: &[f64],
+  tech_coeff: &[f64],
+  leontief_factorization: ExternalPtr<Factorization>,
+  final_demand: RMatrix<f64>,
+  n_countries: usize,
+  threads: usize,
+  #[default = "NULL"] sectors: Nullable<Strings>,
+  #[default = "NULL"] countries: Nullable<Strings>
+) -> Result<List> {
+
+  // get dimensions
+  let n = leontief_factorization.n();
+  if n_countries < 2 || n % n_countries != 0 {
+    return Err("Number of sectors must be a multiple of the number of countries, with at least two countries.".into());
+  }
+  if total_production.len() != n || value_added.len() != n {
+    return Err("value_added must have one element for each sector.".into());
+  }
+  if final_demand.nrows() != n || final_demand.ncols() != n_countries {
+    return Err("final_demand must have one row for each sector and one column for each country.".into());
+  }
+  let n_sectors = n / n_countries;
+  let leontief_inverse = leontief_factorization.inverse_in_pool(threads)?;
+
+  // create faer matrices
+  let tech_coeff_matrix = Mat::from_fn(n, n, |row, col| tech_coeff[col * n + row]);
+  let final_demand_matrix = Mat::from_fn(n, n_countries, |row, col| final_demand[[row, col]]);
+
+  let (terms, exports, local_leontief) = with_threads(threads, || {
+    wwz_decomposition(
+      &tech_coeff_matrix,
+      total_production,
+      leontief_inverse,
+      &final_demand_matrix,
+      value_added,
+      n_countries
+    )
+  })?;
+
+  let local_leontief_inverse = set_list_names(
+    List::from_values(
+      local_leontief
+        .iter()
+        .map(|local| {
+          set_dimnames(RArray::new_matrix(n_sectors, n_sectors, |row, col| local[(row, col)]), &sectors, &sectors)
+        })
+        .collect::<Result<Vec<_>>>()?
+    ),
+    &countries
+  )?;
+
+  Ok(list!(
+    terms = set_dimnames(
+      RArray::new_matrix(terms.nrows(), WWZ_TERMS, |row, col| terms[(row, col)]),
+      &Null,
+      &fixed_labels(&WWZ_TERM_NAMES)
+    )?,
+    exports = exports,
+    local_leontief_inverse = local_leontief_inverse
+  ))
+}
+
+/// Global value chain participation of each country-sector, as \eqn{n x 3} trade-based terms (gross exports,
+/// indirect value added exports and foreign value added in exports) and \eqn{n x 4} production-based forward and
+/// backward terms (pure domestic, traditional trade, simple and complex GVC).
+fn gvc_participation(
+  tech_coeff: &Mat<f64>,
+  total_production: &[f64],
+  leontief_inverse: &Mat<f64>,
+  final_demand: &Mat<f64>,
+  value_added: &[f64],
+  n_countries: usize
+) -> (Mat<f64>, Mat<f64>, Mat<f64>) {
+  let n = total_production.len();
+  let n_sectors = n / n_countries;
+  let country = |i: usize| i / n_sectors;
+
+  // value-added coefficients and value added by origin
+  let va_coeff = value_added_coefficients(value_added, total_production);
+  let global_va = value_added_by_origin(&va_coeff, leontief_inverse, n_countries);
+
+  // bilateral and gross exports
+  let flows = bilateral_exports_all(tech_coeff, total_production, final_demand, n_sectors);
+  let exports = gross_exports(&flows, n_sectors);
+
+  // indirect value added exports, value added of s in exports of r to third countries, v_i B_{ir} E^{rt}
+  let indirect_va: Vec<f64> = (0..n)
+    .into_par_iter()
+    .map(|i| {
+      let origin = country(i);
+      let embodied: f64 = (0..n)
+        .filter(|&j| country(j) != origin)
+        .map(|j| leontief_inverse[(i, j)] * (exports[j] - flows[country(j)][(j % n_sectors, origin)]))
+        .sum();
+      va_coeff[i] * embodied
+    })
+    .collect();
+
+  // foreign value added in exports
+  let foreign_va: Vec<f64> = (0..n)
+    .map(|j| {
+      let foreign: f64 = (0..n_countries).filter(|&o| o != country(j)).map(|o| global_va[(o, j)]).sum();
+      foreign * exports[j]
+    })
+    .collect();
+
+  let trade_based = Mat::from_fn(n, 3, |i, col| match col {
+    0 => exports[i],
+    1 => indirect_va[i],
+    _ => foreign_va[i],
+  });
+
+  // final demand: total, domestic (y_D) and exported (y_F)
+  let total_final: Vec<f64> = (0..n).map(|i| (0..n_countries).map(|c| final_demand[(i, c)]).sum()).collect();
+  let domestic_final: Vec<f64> = (0..n).map(|i| final_demand[(i, country(i))]).collect();
+
+  // domestic and foreign blocks of technical coefficients, L = (I - A_D)^{-1} and A_F = A - A_D
+  let local_leontief = local_leontief_inverses(tech_coeff, n_countries);
+  let foreign_coeff = Mat::from_fn(n, n, |i, j| {
+    if country(i) == country(j) {
+      0.0
+    } else {
+      tech_coeff[(i, j)]
+    }
+  });
+
+  // block-diagonal products with local leontief inverses, L M and M L
+  let local_left = |m: &Mat<f64>| {
+    Mat::from_fn(n, m.ncols(), |i, col| {
+      let offset = country(i) * n_sectors;
+      (0..n_sectors).map(|k| local_leontief[country(i)][(i - offset, k)] * m[(offset + k, col)]).sum::<f64>()
+    })
+  };
+  let local_right = |m: &Mat<f64>| {
+    Mat::from_fn(m.nrows(), n, |row, j| {
+      let offset = country(j) * n_sectors;
+      (0..n_sectors).map(|k| m[(row, offset + k)] * local_leontief[country(j)][(k, j - offset)]).sum::<f64>()
+    })
+  };
+
+  // forward terms, by value-added origin
+  let demand = Mat::from_fn(n, 3, |i, col| match col {
+    0 => domestic_final[i],
+    1 => total_final[i] - domestic_final[i],
+    _ => total_final[i],
+  });
+  let global_output = leontief_inverse * demand.subcols(2, 1);
+  let local_output = local_left(&demand.subcols(0, 2).to_owned());
+  let foreign_demand = Mat::from_fn(n, 2, |i, col| match col {
+    0 => local_output[(i, 0)],
+    _ => global_output[(i, 0)] - local_output[(i, 0)],
+  });
+  let gvc_output = local_left(&(&foreign_coeff * foreign_demand));
+
+  // backward terms, by final production
+  let va_row = Mat::from_fn(1, n, |_, j| va_coeff[j]);
+  let local_va = local_right(&va_row);
+  let gvc_va = &local_va * &foreign_coeff;
+  let gvc_local = local_right(&gvc_va);
+  let gvc_global = &gvc_va * leontief_inverse;
+
+  let production_based = Mat::from_fn(n, 8, |i, col| match col {
+    0 => va_coeff[i] * local_output[(i, 0)],
+    1 => va_coeff[i] * local_output[(i, 1)],
+    2 => va_coeff[i] * gvc_output[(i, 0)],
+    3 => va_coeff[i] * gvc_output[(i, 1)],
+    4 => local_va[(0, i)] * domestic_final[i],
+    5 => local_va[(0, i)] * (total_final[i] - domestic_final[i]),
+    6 => gvc_local[(0, i)] * domestic_final[i],
+    _ => gvc_global[(0, i)] * total_final[i] - gvc_local[(0, i)] * domestic_final[i],
+  });
+
+  (
+    trade_based,
+    production_based.subcols(0, 4).to_owned(),
+    production_based.subcols(4, 4).to_owned()
+  )
+}
+
+#[allow(clippy::too_many_arguments)]
+#[extendr]
+/// @description
+/// Computes global value chain participation and position of each country-sector of a multi-regional input-output
+/// model.
+///
+/// @param value_added
+/// A \eqn{1 x n} vector of value added.
+/// @param total_production
+/// A \eqn{1 x n} vector of total production.
+/// @param tech_coeff
+/// A \eqn{n x n} matrix of technical coefficients.
+/// @param leontief_factorization
+/// The factorization of the Leontief matrix, as returned by `compute_factorization()`.
+/// @param final_demand
+/// A \eqn{n x r} matrix of final demand, one column for each consuming country.
+/// @param n_countries
+/// Number of countries.
+/// @param threads
+/// Number of threads. 0 means the session-wide setting.
+/// @param labels
+/// Optional labels of the country-sectors, attached as row names.
+///
+/// @details
+/// Trade-based terms follow Koopman et al.: gross exports, indirect value added exports (domestic value added in
+/// exports of intermediates that the importer re-exports to third countries) and foreign value added in exports.
+///
+/// Production-based terms follow Wang et al.: with local Leontief inverses \eqn{L = (I - A_D)^{-1}} and foreign
+/// coefficients \eqn{A_F = A - A_D}, value added is split forward as
+/// \deqn{\hat{V} B Y = \hat{V} L Y_D + \hat{V} L Y_F + \hat{V} L A_F L Y_D + \hat{V} L A_F (B Y - L Y_D)}
+/// into pure domestic, traditional trade, simple and complex GVC terms, and final production is split backward by the
+/// same terms of \eqn{V B \hat{Y}}.
+///
+/// @return
+/// A list with a \eqn{n x 3} matrix of trade-based terms and \eqn{n x 4} matrices of forward and backward
+/// production-based terms, with columns named after the terms.
+///
+/// @noRd
+fn compute_gvc_participation(
+  value_added: &[f64],
+  total_production: &[f64],
+  tech_coeff: &[f64],
+  leontief_factorization: ExternalPtr<Factorization>,
+  final_demand: RMatrix<f64>,
+  n_countries: usize,
+  threads: usize,
+  #[default = "NULL"] labels: Nullable<Strings>
+) -> Result<List> {
+
+  // get dimensions
+  let n = leontief_factorization.n();
+  if n_countries == 0 || n % n_countries != 0 {
+    return Err("Number of sectors must be a multiple of the number of countries.".into());
+  }
+  if total_production.len() != n || value_added.len() != n {
+    return Err("value_added must have one element for each sector.".into());
+  }
+  if final_demand.nrows() != n || final_demand.ncols() != n_countries {
+    return Err("final_demand must have one row for each sector and one column for each country.".into());
+  }
+  let leontief_inverse = leontief_factorization.inverse_in_pool(threads)?;
+
+  // create faer matrices
+  let tech_coeff_matrix = Mat::from_fn(n, n, |row, col| tech_coeff[col * n + row]);
+  let final_demand_matrix = Mat::from_fn(n, n_countries, |row, col| final_demand[[row, col]]);
+
+  let (trade_based, forward, backward) = with_threads(threads, || {
+    gvc_participation(
+      &tech_coeff_matrix,
+      total_production,
+      leontief_inverse,
+      &final_demand_matrix,
+      value_added,
+      n_countries
+    )
+  })?;
+
+  Ok(list!(
+    trade_based = set_dimnames(
+      RArray::new_matrix(n, 3, |row, col| trade_based[(row, col)]),
+      &labels,
+      &fixed_labels(&["exports", "indirect_value_added", "foreign_value_added"])
+    )?,
+    forward = set_dimnames(
+      RArray::new_matrix(n, 4, |row, col| forward[(row, col)]),
+      &labels,
+      &fixed_labels(&GVC_TERM_NAMES)
+    )?,
+    backward = set_dimnames(
+      RArray::new_matrix(n, 4, |row, col| backward[(row, col)]),
+      &labels,
+      &fixed_labels(&GVC_TERM_NAMES)
+    )?
+  ))
+}
+
+#[extendr]
+/// @description
+/// Computes country-by-country block sums of the Leontief inverse of a multi-regional input-output model, along with
+/// intra-regional, inter-regional and spillover multipliers.
+///
+/// @param leontief_factorization
+/// The factorization of the Leontief matrix, as returned by `compute_factorization()`.
+/// @param n_countries
+/// Number of countries.
+/// @param threads
+/// Number of threads. 0 means the session-wide setting.
+/// @param labels
+/// Optional labels of the country-sectors, attached as names.
+/// @param countries
+/// Optional labels of the countries, attached as names.
+///
+/// @details
+/// Sectors are ordered by country, so that the block \eqn{(r, s)} of the Leontief inverse holds the output of
+/// sectors of country \eqn{r} induced by unit final demand shocks in sectors of country \eqn{s}
+/// \insertCite{miller_input-output_2009}{fio}. Sums over the rows of each block, for each column, are the
+/// inter-regional multipliers of a shock in that country-sector, the diagonal block giving its intra-regional
+/// multiplier and the remaining blocks its spillover multiplier. Block sums \eqn{S_{rs}} give the net spillover
+/// \eqn{S_{rs} - S_{sr}} between countries, the spillover out of a country, \eqn{\sum_{s \neq r} S_{sr}}, and into
+/// it, \eqn{\sum_{s \neq r} S_{rs}}. Everything follows from a single pass over the Leontief inverse.
+///
+/// @return
+/// A list with a \eqn{n x r} matrix of inter-regional multipliers, \eqn{1 x n} vectors of intra-regional, spillover
+/// and total multipliers, \eqn{r x r} matrices of block sums and net spillovers and \eqn{1 x r} vectors of
+/// self-reliance (average intra-regional multiplier), spillover out and spillover in, by country.
+///
+/// @references
+/// \insertAllCited{}
+///
+/// @noRd
+fn compute_multiregional_blocks(
+  leontief_factorization: ExternalPtr<Factorization>,
+  n_countries: usize,
+  threads: usize,
+  #[default = "NULL"] labels: Nullable<Strings>,
+  #[default = "NULL"] countries: Nullable<Strings>
+) -> Result<List> {
+
+  // get dimensions
+  let n = leontief_factorization.n();
+  if n_countries == 0 || n % n_countries != 0 {
+    return Err("Number of sectors must be a multiple of the number of countries.".into());
+  }
+  let n_sectors = n / n_countries;
+  let leontief_inverse = leontief_factorization.inverse_in_pool(threads)?;
+
+  // multipliers by country where output is induced, for each shock
+  let by_country = with_threads(threads, || country_column_sums(leontief_inverse, n_countries))?;
+
+  // intra-regional, spillover and total multipliers
+  let intra_regional: Vec<f64> = (0..n).map(|col| by_country[(col / n_sectors, col)]).collect();
+  let total: Vec<f64> = (0..n).map(|col| by_country.col(col).iter().sum()).collect();
+  let spillover: Vec<f64> = (0..n).map(|col| total[col] - intra_regional[col]).collect();
+
+  // block sums, by country of output (rows) and of shocks (columns)
+  let block_sums = Mat::from_fn(n_countries, n_countries, |r, s| {
+    (s * n_sectors..(s + 1) * n_sectors).map(|col| by_country[(r, col)]).sum::<f64>()
+  });
+  let net_spillover = Mat::from_fn(n_countries, n_countries, |r, s| block_sums[(r, s)] - block_sums[(s, r)]);
+
+  // self-reliance and spillovers out of and into each country
+  let self_reliance: Vec<f64> = (0..n_countries).map(|r| block_sums[(r, r)] / n_sectors as f64).collect();
+  let spillover_out: Vec<f64> = (0..n_countries)
+    .map(|r| (0..n_countries).filter(|&s| s != r).map(|s| block_sums[(s, r)]).sum())
+    .collect();
+  let spillover_in: Vec<f64> = (0..n_countries)
+    .map(|r| (0..n_countries).filter(|&s| s != r).map(|s| block_sums[(r, s)]).sum())
+    .collect();
+
+  Ok(list!(
+    inter_regional = set_dimnames(
+      RArray::new_matrix(n, n_countries, |row, col| by_country[(col, row)]),
+      &labels,
+      &countries
+    )?,
+    intra_regional = set_names(intra_regional, &labels)?,
+    spillover = set_names(spillover, &labels)?,
+    total = set_names(total, &labels)?,
+    block_sums = set_dimnames(
+      RArray::new_matrix(n_countries, n_countries, |row, col| block_sums[(row, col)]),
+      &countries,
+      &countries
+    )?,
+    net_spillover = set_dimnames(
+      RArray::new_matrix(n_countries, n_countries, |row, col| net_spillover[(row, col)]),
+      &countries,
+      &countries
+    )?,
+    self_reliance = set_names(self_reliance, &countries)?,
+    spillover_out = set_names(spillover_out, &countries)?,
+    spillover_in = set_names(spillover_in, &countries)?
+  ))
+}
+
+#[extendr]
+/// @description
+/// Computes the spillover matrix of a multi-regional input-output model, the Leontief inverse with its diagonal
+/// (intra-regional) blocks set to zero.
+///
+/// @param leontief_factorization
+/// The factorization of the Leontief matrix, as returned by `compute_factorization()`.
+/// @param n_countries
+/// Number of countries.
+/// @param threads
+/// Number of threads. 0 means the session-wide setting.
+/// @param row_labels
+/// Optional labels of the rows (country-sectors), attached to the result.
+/// @param col_labels
+/// Optional labels of the columns (country-sectors), attached to the result.
+///
+/// @return
+/// A \eqn{n x n} matrix of inter-regional spillover effects.
+///
+/// @noRd
+fn compute_spillover_matrix(
+  leontief_factorization: ExternalPtr<Factorization>,
+  n_countries: usize,
+  threads: usize,
+  #[default = "NULL"] row_labels: Nullable<Strings>,
+  #[default = "NULL"] col_labels: Nullable<Strings>
+) -> Result<RArray<f64, [usize;2]>> {
+
+  // get dimensions
+  let n = leontief_factorization.n();
+  if n_countries == 0 || n % n_countries != 0 {
+    return Err("Number of sectors must be a multiple of the number of countries.".into());
+  }
+  let n_sectors = n / n_countries;
+  let leontief_inverse = leontief_factorization.inverse_in_pool(threads)?;
+
+  // mask diagonal blocks
+  set_dimnames(
+    RArray::new_matrix(n, n, |row, col| {
+      if row / n_sectors == col / n_sectors { 0.0 } else { leontief_inverse[(row, col)] }
+    }),
+    &row_labels,
+    &col_labels
+  )
+}
+
+#[extendr]
+/// @description
+/// Decomposes the Leontief inverse of a multi-regional input-output model into intra-regional, spillover and
+/// feedback effects between countries.
+///
+/// @param tech_coeff
+/// A \eqn{n x n} matrix of technical coefficients.
+/// @param leontief_factorization
+/// The factorization of the Leontief matrix, as returned by `compute_factorization()`.
+/// @param n_countries
+/// Number of countries.
+/// @param threads
+/// Number of threads. 0 means the session-wide setting.
+/// @param labels
+/// Optional labels of the country-sectors, attached as names.
+/// @param countries
+/// Optional labels of the countries, attached as names.
+///
+/// @details
+/// Technical coefficients are split into diagonal (intra-regional) blocks, \eqn{A_d}, and off-diagonal blocks,
+/// \eqn{A_o}. With \eqn{M_1 = (I - A_d)^{-1}} and \eqn{A^* = M_1 A_o}, the Leontief inverse of \eqn{r} countries
+/// factors as \eqn{L = M_3 M_2 M_1} \insertCite{round_decomposing_1985,miller_input-output_2009}{fio}, with
+///
+/// \deqn{M_2 = I + A^* + \dots + A^{*(r - 1)}, \quad M_3 = (I - A^{*r})^{-1}}
+///
+/// so that \eqn{L = M_1 + (M_2 - I) M_1 + (M_3 - I) M_2 M_1}, the sum of intra-regional, spillover and feedback
+/// effects. Effects are summed over the sectors of the country where output is induced (rows) and of the country of
+/// the final demand shock (columns). Intra-regional effects include the initial shock.
+///
+/// Miller's feedback measure of a country-sector is the difference between the own-country output multiplier of the
+/// multi-regional model and that of the country on its own, \eqn{(I - A_{rr})^{-1}}
+/// \insertCite{miller_interregional_1966}{fio}.
+///
+/// @return
+/// A list with \eqn{r x r} matrices of intra-regional, spillover and feedback effects and \eqn{1 x n} vectors of
+/// own-country output multipliers of the country on its own and within the multi-regional model.
+///
+/// @references
+/// \insertAllCited{}
+///
+/// @noRd
+fn compute_regional_decomposition(
+  tech_coeff: &[f64],
+  leontief_factorization: ExternalPtr<Factorization>,
+  n_countries: usize,
+  threads: usize,
+  #[default = "NULL"] labels: Nullable<Strings>,
+  #[default = "NULL"] countries: Nullable<Strings>
+) -> Result<List> {
+
+  // get dimensions
+  let n = leontief_factorization.n();
+  if n_countries == 0 || n % n_countries != 0 {
+    return Err("Number of sectors must be a multiple of the number of countries.".into());
+  }
+  if tech_coeff.len() != n * n {
+    return Err("tech_coeff must have one row and one column for each sector.".into());
+  }
+  let n_sectors = n / n_countries;
+  let leontief_inverse = leontief_factorization.inverse_in_pool(threads)?;
+
+  // create faer matrix
+  let tech_coeff_matrix = Mat::from_fn(n, n, |row, col| tech_coeff[col * n + row]);
+
+  let (intra_regional, spillover, feedback, local, regional) = with_threads(threads, || {
+    let (intra_regional, spillover, feedback, local) =
+      regional_decomposition(&tech_coeff_matrix, leontief_inverse, n_countries);
+    let regional = country_column_sums(leontief_inverse, n_countries);
+    (intra_regional, spillover, feedback, local, regional)
+  })?;
+
+  // own-country multipliers, alone and within the multi-regional model
+  let local_multiplier: Vec<f64> = (0..n).map(|col| local[(col / n_sectors, col)]).collect();
+  let regional_multiplier: Vec<f64> = (0..n).map(|col| regional[(col / n_sectors, col)]).collect();
+
+  let block_matrix = |effects: &Mat<f64>| {
+    set_dimnames(
+      RArray::new_matrix(n_countries, n_countries, |row, col| effects[(row, col)]),
+      &countries,
+      &countries
+    )
+  };
+
+  Ok(list!(
+    intra_regional = block_matrix(&intra_regional)?,
+    spillover = block_matrix(&spillover)?,
+    feedback = block_matrix(&feedback)?,
+    local_multiplier = set_names(local_multiplier, &labels)?,
+    regional_multiplier = set_names(regional_multiplier, &labels)?
+  ))
+}
+
+// Macro to generate exports.
+// This ensures exported functions are registered with R.
+// See corresponding C code in `entrypoint.c`.
+extendr_module! {
+  mod mrio;
+  fn compute_embodied_trade;
+  fn compute_tiva;
+  fn compute_wwz_decomposition;
+  fn compute_gvc_participation;
+  fn compute_multiregional_blocks;
+  fn compute_spillover_matrix;
+  fn compute_regional_decomposition;
+}
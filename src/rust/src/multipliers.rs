@@ -2,57 +2,185 @@ use extendr_api::prelude::*;
 use faer::{ColRef, Mat};
 use rayon::prelude::*;
 
+use crate::labels::{set_dimnames, set_names};
+
+/// Column sums of a square matrix, given in column-major order.
+fn column_sums(matrix: &[f64]) -> Vec<f64> {
+    // get dimensions (square root of length)
+    let n = (matrix.len() as f64).sqrt() as usize;
+
+    matrix
+        .par_chunks(n)
+        .map(|col| col.iter().sum())
+        .collect::<Vec<f64>>()
+}
+
+/// Value-added requirements, \eqn{v_j / x_j}, zero for sectors without output.
+fn requirements_value_added(value_added_element: &[f64], total_production: &[f64]) -> Vec<f64> {
+    value_added_element
+        .iter()
+        .zip(total_production.iter())
+        .map(|(&value_added, &production)| if production == 0.0 { 0.0 } else { value_added / production })
+        .collect::<Vec<f64>>()
+}
+
+/// Inverse of total production, \eqn{1 / x_i}, zero for sectors without output.
+pub(crate) fn inverse_production(total_production: &[f64]) -> Vec<f64> {
+    requirements_value_added(&vec![1.0; total_production.len()], total_production)
+}
+
+/// Generator matrix of value-added requirements, \eqn{\hat{v} L}.
+fn generator_value_added(value_added_requirements: &[f64], leontief_inverse_matrix: &RMatrix<f64>) -> Mat<f64> {
+    let n = leontief_inverse_matrix.nrows();
+
+    let leontief_inverse_matrix_faer =
+        Mat::from_fn(n, n, |row, col| leontief_inverse_matrix[[row, col]]);
+    let value_added_requirements_column = ColRef::from_slice(value_added_requirements);
+
+    // create diagonal matrix from value_added requirements
+    let value_added_requirements_matrix_diag = ColRef::as_diagonal(value_added_requirements_column);
+
+    // calculate generator value_added
+    value_added_requirements_matrix_diag * leontief_inverse_matrix_faer
+}
+
+/// Value-added multipliers, column sums of the generator matrix.
+fn multiplier_value_added(value_added_requirements: &[f64], leontief_inverse_matrix: &RMatrix<f64>) -> Vec<f64> {
+    generator_value_added(value_added_requirements, leontief_inverse_matrix)
+        .col_iter()
+        .map(|col| col.iter().sum())
+        .collect::<Vec<f64>>()
+}
+
 #[extendr]
 /// Computes output multiplier.
 /// @param leontief_inverse_matrix The open model Leontief inverse matrix.
+/// @param labels Optional labels of the sectors, attached as names.
 /// @return A 1xn vector of type I output multipliers.
 /// @noRd
-fn compute_multiplier_output(leontief_inverse_matrix: &[f64]) -> Vec<f64> {
-    // get dimensions (square root of length)
-    let n = (leontief_inverse_matrix.len() as f64).sqrt() as usize;
-
+fn compute_multiplier_output(
+    leontief_inverse_matrix: &[f64],
+    #[default = "NULL"] labels: Nullable<Strings>,
+) -> Result<Doubles> {
     // get column sums
-    leontief_inverse_matrix
-        .par_chunks(n)
-        .map(|col| col.iter().sum())
-        .collect::<Vec<f64>>()
+    set_names(column_sums(leontief_inverse_matrix), &labels)
 }
 
 #[extendr]
 /// Computes direct output multiplier.
 /// @param technical_coefficients_matrix The open model technical coefficients matrix.
+/// @param labels Optional labels of the sectors, attached as names.
 /// @return A 1xn vector of direct output multipliers.
 /// @noRd
-fn compute_multiplier_output_direct(technical_coefficients_matrix: &[f64]) -> Vec<f64> {
-    // get dimensions (square root of length)
-    let n = (technical_coefficients_matrix.len() as f64).sqrt() as usize;
-
+fn compute_multiplier_output_direct(
+    technical_coefficients_matrix: &[f64],
+    #[default = "NULL"] labels: Nullable<Strings>,
+) -> Result<Doubles> {
     // get column sums
-    technical_coefficients_matrix
-        .par_chunks(n)
-        .map(|col| col.iter().sum())
-        .collect::<Vec<f64>>()
+    set_names(column_sums(technical_coefficients_matrix), &labels)
 }
 
 #[extendr]
 /// Computes indirect output multiplier.
 /// @param technical_coefficients_matrix The open model technical coefficients matrix.
 /// @param leontief_inverse_matrix The open model Leontief inverse matrix.
+/// @param labels Optional labels of the sectors, attached as names.
 /// @return A 1xn vector of indirect output multipliers.
 /// @noRd
 fn compute_multiplier_output_indirect(
     technical_coefficients_matrix: &[f64],
     leontief_inverse_matrix: &[f64],
-) -> Vec<f64> {
-    let total_effects = compute_multiplier_output(leontief_inverse_matrix);
-    let direct_effects = compute_multiplier_output_direct(technical_coefficients_matrix);
+    #[default = "NULL"] labels: Nullable<Strings>,
+) -> Result<Doubles> {
+    let total_effects = column_sums(leontief_inverse_matrix);
+    let direct_effects = column_sums(technical_coefficients_matrix);
 
     // get indirect effects
-    total_effects
+    let indirect_effects = total_effects
         .iter()
         .zip(direct_effects.iter())
         .map(|(total, direct)| total - direct)
-        .collect::<Vec<f64>>()
+        .collect::<Vec<f64>>();
+
+    set_names(indirect_effects, &labels)
+}
+
+#[extendr]
+/// Computes net output multiplier.
+/// @param multipliers The simple output multipliers, of the open or closed model.
+/// @param intermediate_transactions The intermediate transactions matrix, of the same model.
+/// @param total_production The total production vector.
+/// @param labels Optional labels of the sectors, attached as names.
+/// @details Output multipliers are weighted by the final-demand share of output of each sector,
+/// \eqn{1 - \sum_k z_{jk} / x_j}, so that they don't double count output when added across sectors
+/// \insertCite{oosterhaven_net_2002}{fio}. In a closed model, sales to endogenous sectors aren't final demand.
+/// Sectors without output have zero net multipliers.
+/// @return A 1xn vector of net output multipliers.
+/// @references \insertAllCited{}
+/// @noRd
+fn compute_multiplier_output_net(
+    multipliers: &[f64],
+    intermediate_transactions: &[f64],
+    total_production: &[f64],
+    #[default = "NULL"] labels: Nullable<Strings>,
+) -> Result<Doubles> {
+    // get dimensions
+    let n = total_production.len();
+    if multipliers.len() != n || intermediate_transactions.len() != n * n {
+        return Err("multipliers and intermediate_transactions must match total_production.".into());
+    }
+
+    // final-demand share of output, from intermediate sales
+    let final_demand_share = (0..n)
+        .map(|j| {
+            let sales = (0..n).map(|k| intermediate_transactions[k * n + j]).sum::<f64>();
+            if total_production[j] == 0.0 { 0.0 } else { 1.0 - sales / total_production[j] }
+        })
+        .collect::<Vec<f64>>();
+
+    // weight simple multipliers by final-demand shares
+    let net_effects = multipliers
+        .iter()
+        .zip(final_demand_share.iter())
+        .map(|(total, share)| total * share)
+        .collect::<Vec<f64>>();
+
+    set_names(net_effects, &labels)
+}
+
+#[extendr]
+/// Computes output-to-output multiplier.
+/// @param leontief_inverse_matrix The Leontief inverse matrix, of the open or closed model.
+/// @param total_production The total production vector.
+/// @param labels Optional labels of the sectors, attached as names.
+/// @details Column sums of \eqn{\hat{x}^{-1} L \hat{x}}, the Leontief inverse in terms of shares of output of
+/// each sector \insertCite{miller_input-output_2009}{fio}. Sectors without output neither add to nor receive
+/// output-to-output effects.
+/// @return A 1xn vector of output-to-output multipliers.
+/// @references \insertAllCited{}
+/// @noRd
+fn compute_multiplier_output_to_output(
+    leontief_inverse_matrix: &[f64],
+    total_production: &[f64],
+    #[default = "NULL"] labels: Nullable<Strings>,
+) -> Result<Doubles> {
+    // get dimensions
+    let n = total_production.len();
+    if leontief_inverse_matrix.len() != n * n {
+        return Err("leontief_inverse_matrix must have one row and one column for each sector.".into());
+    }
+
+    // column sums of x^-1 L x
+    let inverse = inverse_production(total_production);
+    let output_effects = leontief_inverse_matrix
+        .par_chunks(n.max(1))
+        .zip(total_production.par_iter())
+        .map(|(col, production)| {
+            col.iter().zip(inverse.iter()).map(|(l, inverse)| l * inverse).sum::<f64>() * production
+        })
+        .collect::<Vec<f64>>();
+
+    set_names(output_effects, &labels)
 }
 
 #[extendr]
@@ -67,10 +195,11 @@ fn compute_multiplier_output_indirect(
 /// 2. computing the generator matrix for a given value-added vector;
 /// 3. and, finally, computing the multiplier for a given value-added vector.
 ///
-/// Current implementation follows \insertCite{vale_alise_2020}{fio}.
+/// Current implementation follows \insertCite{vale_alise_2020}{fio}. Sectors without output have zero requirements.
 ///
 /// @param value_added_element A value-added vector.
 /// @param total_production The total production vector.
+/// @param labels Optional labels of the sectors, attached as names.
 /// @return A 1xn vector of a given value-added coefficients.
 ///
 /// @references \insertAllCited{}
@@ -87,38 +216,34 @@ fn compute_multiplier_output_indirect(
 fn compute_requirements_value_added(
     value_added_element: &[f64],
     total_production: &[f64],
-) -> Vec<f64> {
-    value_added_element
-        .iter()
-        .zip(total_production.iter())
-        .map(|(value_added, production)| value_added / production)
-        .collect::<Vec<f64>>()
+    #[default = "NULL"] labels: Nullable<Strings>,
+) -> Result<Doubles> {
+    set_names(requirements_value_added(value_added_element, total_production), &labels)
 }
 
 #[extendr]
 /// Computes generator matrix for a given value-added vector.
 /// @param value_added_requirements The coefficients for a given value-added vector.
 /// @param leontief_inverse_matrix The open model Leontief inverse matrix.
+/// @param labels Optional labels of the sectors, attached as row and column names.
 /// @return A nxn matrix of an value-added vector generator.
 /// @noRd
 fn compute_generator_value_added(
     value_added_requirements: Vec<f64>,
     leontief_inverse_matrix: RMatrix<f64>,
-) -> RMatrix<f64> {
+    #[default = "NULL"] labels: Nullable<Strings>,
+) -> Result<RMatrix<f64>> {
     let n = leontief_inverse_matrix.nrows();
 
-    let leontief_inverse_matrix_faer =
-        Mat::from_fn(n, n, |row, col| leontief_inverse_matrix[[row, col]]);
-    let value_added_requirements_column = ColRef::from_slice(&value_added_requirements);
-
-    // create diagonal matrix from value_added requirements
-    let value_added_requirements_matrix_diag = ColRef::as_diagonal(value_added_requirements_column);
-
     // calculate generator value_added
-    let generator_value_added = value_added_requirements_matrix_diag * leontief_inverse_matrix_faer;
+    let generator_value_added = generator_value_added(&value_added_requirements, &leontief_inverse_matrix);
 
     // convert to R matrix
-    RMatrix::new_matrix(n, n, |row, col| generator_value_added[(row, col)])
+    set_dimnames(
+        RMatrix::new_matrix(n, n, |row, col| generator_value_added[(row, col)]),
+        &labels,
+        &labels,
+    )
 }
 
 #[extendr]
@@ -137,6 +262,7 @@ fn compute_generator_value_added(
 ///
 /// @param value_added_requirements The coefficients for a given value-added vector.
 /// @param leontief_inverse_matrix The open model Leontief inverse matrix.
+/// @param labels Optional labels of the sectors, attached as names.
 ///
 /// @return A 1xn vector of a given value-added multipliers.
 ///
@@ -161,22 +287,10 @@ fn compute_generator_value_added(
 fn compute_multiplier_value_added(
     value_added_requirements: Vec<f64>,
     leontief_inverse_matrix: RMatrix<f64>,
-) -> Vec<f64> {
-    // dimensions
-    let n = leontief_inverse_matrix.nrows();
-
-    let generator_value_added =
-        compute_generator_value_added(value_added_requirements, leontief_inverse_matrix);
-
-    // convert to faer matrix
-    let generator_value_added_faer =
-        Mat::from_fn(n, n, |row, col| generator_value_added[[row, col]]);
-
-    // get column sums
-    generator_value_added_faer
-        .col_iter()
-        .map(|col| col.iter().sum())
-        .collect::<Vec<f64>>()
+    #[default = "NULL"] labels: Nullable<Strings>,
+) -> Result<Doubles> {
+    // get column sums of the generator matrix
+    set_names(multiplier_value_added(&value_added_requirements, &leontief_inverse_matrix), &labels)
 }
 
 #[extendr]
@@ -184,24 +298,26 @@ fn compute_multiplier_value_added(
 /// @param value_added_element An value-added vector.
 /// @param total_production The total production vector.
 /// @param leontief_inverse_matrix The open model Leontief inverse matrix.
+/// @param labels Optional labels of the sectors, attached as names.
 /// @return A 1xn vector of indirect multipliers for a given value-added vector.
 /// @noRd
 fn compute_multiplier_value_added_indirect(
     value_added_element: &[f64],
     total_production: &[f64],
     leontief_inverse_matrix: RMatrix<f64>,
-) -> Vec<f64> {
-    let value_added_requirements =
-        compute_requirements_value_added(value_added_element, total_production);
-    let total_effects =
-        compute_multiplier_value_added(value_added_requirements.clone(), leontief_inverse_matrix);
+    #[default = "NULL"] labels: Nullable<Strings>,
+) -> Result<Doubles> {
+    let value_added_requirements = requirements_value_added(value_added_element, total_production);
+    let total_effects = multiplier_value_added(&value_added_requirements, &leontief_inverse_matrix);
 
     // compute indirect effects
-    total_effects
+    let indirect_effects = total_effects
         .iter()
         .zip(value_added_requirements.iter())
         .map(|(total, direct)| total - direct)
-        .collect::<Vec<f64>>()
+        .collect::<Vec<f64>>();
+
+    set_names(indirect_effects, &labels)
 }
 
 // Macro to generate exports.
@@ -212,6 +328,8 @@ extendr_module! {
   fn compute_multiplier_output;
   fn compute_multiplier_output_direct;
   fn compute_multiplier_output_indirect;
+  fn compute_multiplier_output_net;
+  fn compute_multiplier_output_to_output;
   fn compute_requirements_value_added;
   fn compute_generator_value_added;
   fn compute_multiplier_value_added;
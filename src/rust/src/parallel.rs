@@ -1,40 +1,93 @@
 use extendr_api::prelude::*;
-use faer::Par;
-use std::panic;
+use rayon::{ThreadPool, ThreadPoolBuilder};
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc, Mutex,
+};
 
-/// @description
-/// Get the global parallelism settings from faer
-/// @noRd
-fn get_parallelism_settings() -> Result<usize> {
-    let result = panic::catch_unwind(|| faer::get_global_parallelism());
+// session-wide number of threads, 0 means all available threads
+static MAX_THREADS: AtomicUsize = AtomicUsize::new(0);
+
+// thread pool of the session-wide setting, the only one kept between computations
+static SESSION_POOL: Mutex<Option<Arc<ThreadPool>>> = Mutex::new(None);
+
+/// Number of threads available to the process.
+fn available_threads() -> usize {
+    std::thread::available_parallelism().map(|threads| threads.get()).unwrap_or(1)
+}
+
+/// Resolves the number of threads of a computation: the per-call setting if positive, otherwise the session-wide
+/// setting, otherwise all available threads. Per-call settings above the available threads are capped to them, as
+/// the session-wide setting is.
+pub(crate) fn resolve_threads(threads: usize) -> usize {
+    let available = available_threads();
+    if threads > 0 {
+        return threads.min(available);
+    }
+    match MAX_THREADS.load(Ordering::Relaxed) {
+        0 => available,
+        max_threads => max_threads,
+    }
+}
+
+/// Builds a thread pool with the given number of threads.
+fn build_pool(num_threads: usize) -> Result<Arc<ThreadPool>> {
+    let pool = ThreadPoolBuilder::new()
+        .num_threads(num_threads)
+        .build()
+        .map_err(|e| Error::Other(format!("Could not build a pool of {} threads: {}", num_threads, e)))?;
+
+    Ok(Arc::new(pool))
+}
 
-    match result {
-        Ok(faer::Par::Rayon(n)) => Ok(n.get()),
-        Ok(faer::Par::Seq) => Ok(1),
-        Err(_) => Err("Parallelism via faer was not set yet".into()),
+/// Gets a thread pool for a computation: the session pool, built on first use, if the computation follows the
+/// session-wide setting, otherwise a pool built for this computation only.
+pub(crate) fn thread_pool(threads: usize) -> Result<Arc<ThreadPool>> {
+    let num_threads = resolve_threads(threads);
+    let mut session_pool = SESSION_POOL
+        .lock()
+        .map_err(|_| Error::Other("Thread pools are unavailable.".into()))?;
+
+    match session_pool.as_ref() {
+        Some(pool) if pool.current_num_threads() == num_threads => Ok(Arc::clone(pool)),
+        _ if threads > 0 => build_pool(num_threads),
+        _ => {
+            let pool = build_pool(num_threads)?;
+            *session_pool = Some(Arc::clone(&pool));
+            Ok(pool)
+        },
     }
 }
 
+/// Runs a computation in a scoped thread pool.
+///
+/// Both Rayon iterators and faer linear algebra (which follows the size of the current Rayon pool) run within the
+/// pool, so concurrent computations may use different numbers of threads.
+pub(crate) fn with_threads<T, F>(threads: usize, op: F) -> Result<T>
+where
+    T: Send,
+    F: FnOnce() -> T + Send,
+{
+    let pool = thread_pool(threads)?;
+    Ok(pool.install(op))
+}
+
 #[extendr]
 /// Sets max number of threads used by fio
 ///
 /// @details
-/// Calling this function sets a global limit of threads to Rayon crate, affecting
-/// all computations that runs in parallel by default.
-///
-/// Default behavior of Rayon is to use all available threads (including logical).
-/// Setting to 1 will result in single threaded (sequential) computations.
+/// Sets the session-wide number of threads used by computations that run in parallel. Computations run in a
+/// session Rayon thread pool of that size, which is kept between computations, unless a number of threads is given
+/// to the computation itself, in which case a pool is built for that computation only.
 ///
-/// Initialization of the global thread pool happens exactly once.
-/// Once started, the configuration cannot be changed in the current session.
-/// If `set_max_threads()` is called again in the same session, it'll result
-/// in an error.
+/// The setting can be changed any number of times in the same session, replacing the session pool.
 ///
 /// @param max_threads Int.
-/// Default is 0 (all threads available). 1 means single threaded.
+/// 0 means all threads available. 1 means single threaded. Values above the number of available threads are capped
+/// to it.
 ///
 /// @return
-/// This functions does not return a value.
+/// The new number of threads.
 ///
 /// @examples
 /// intermediate_transactions <- matrix(c(1, 2, 3, 4, 5, 6, 7, 8, 9), 3, 3)
@@ -45,29 +98,39 @@ fn get_parallelism_settings() -> Result<usize> {
 /// my_iom$set_max_threads(1L)
 ///
 /// @noRd
-fn set_max_threads(max_threads: usize) {
-
-    let threads = get_parallelism_settings().unwrap_or(0);
-    println!("Previous global thread pool size: {}", threads);
-    let num_threads = if max_threads == 0 {
-        num_cpus::get()
-    } else {
-      max_threads
+fn set_max_threads(max_threads: usize) -> Result<usize> {
+    let num_threads = match max_threads {
+        0 => available_threads(),
+        max_threads => max_threads.min(available_threads()),
     };
+    MAX_THREADS.store(num_threads, Ordering::Relaxed);
 
-    match panic::catch_unwind(|| faer::set_global_parallelism(Par::rayon(num_threads))) {
-      Ok(_) => (),
-      Err(_) => panic!(
-        "Global thread pool was already initialized with {} threads. Cannot change it in this session.",
-        threads
-      ),
-    }
+    // replace the session pool
+    let mut session_pool = SESSION_POOL
+        .lock()
+        .map_err(|_| Error::Other("Thread pools are unavailable.".into()))?;
+    *session_pool = Some(build_pool(num_threads)?);
 
-    println!("New global thread pool size: {}", num_threads);
+    Ok(num_threads)
+}
 
-  }
+#[extendr]
+/// Gets max number of threads used by fio
+///
+/// @details
+/// Gets the session-wide number of threads used by computations that run in parallel, as set by
+/// `set_max_threads()`, or all available threads if not set.
+///
+/// @return
+/// The number of threads.
+///
+/// @noRd
+fn get_max_threads() -> Result<usize> {
+    Ok(resolve_threads(0))
+}
 
 extendr_module! {
   mod parallel;
   fn set_max_threads;
-}
\ No newline at end of file
+  fn get_max_threads;
+}
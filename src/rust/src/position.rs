@@ -0,0 +1,185 @@
+use extendr_api::prelude::*;
+use faer::Mat;
+use rayon::prelude::*;
+
+use crate::factorization::Factorization;
+use crate::labels::{set_dimnames, set_names};
+use crate::parallel::with_threads;
+
+// entries of L - I below this value are taken as zero, having no propagation path
+const TOLERANCE: f64 = 1e-12;
+
+/// Averages of the sectors of each country, weighted by total production.
+fn country_weighted_mean(values: &[f64], total_production: &[f64], n_countries: usize) -> Vec<f64> {
+  let n_sectors = values.len() / n_countries;
+
+  (0..n_countries)
+    .map(|r| {
+      let sectors = r * n_sectors..(r + 1) * n_sectors;
+      let output: f64 = total_production[sectors.clone()].iter().sum();
+      let total: f64 = sectors.map(|i| values[i] * total_production[i]).sum();
+      if output == 0.0 { 0.0 } else { total / output }
+    })
+    .collect()
+}
+
+/// Average propagation lengths between countries, the lengths between their sectors weighted by \eqn{L - I}, which
+/// add up \eqn{L (L - I)} over the pairs with a propagation path.
+fn country_propagation_length(propagation: &Mat<f64>, indirect: &Mat<f64>, n_countries: usize) -> Mat<f64> {
+  let n_sectors = indirect.nrows() / n_countries;
+
+  Mat::from_fn(n_countries, n_countries, |r, s| {
+    let (mut total, mut total_weight) = (0.0, 0.0);
+    for col in s * n_sectors..(s + 1) * n_sectors {
+      for row in r * n_sectors..(r + 1) * n_sectors {
+        if indirect[(row, col)] > TOLERANCE {
+          total += propagation[(row, col)];
+          total_weight += indirect[(row, col)];
+        }
+      }
+    }
+    if total_weight > 0.0 { total / total_weight } else { 0.0 }
+  })
+}
+
+#[extendr]
+/// @description
+/// Computes upstreamness, downstreamness and average propagation length of each sector.
+///
+/// @param leontief_factorization
+/// The factorization of the Leontief matrix, as returned by `compute_factorization()`.
+/// @param ghosh_factorization
+/// The factorization of the Ghosh matrix, as returned by `compute_factorization()`.
+/// @param total_production
+/// A \eqn{1 x n} vector of total production, weighting the country averages.
+/// @param threads
+/// Number of threads. 0 means the session-wide setting.
+/// @param labels
+/// Optional labels of the sectors, attached as names.
+/// @param countries
+/// Optional labels of the countries of a multi-regional model, whose sectors are ordered by country. If given,
+/// results are also aggregated by country.
+///
+/// @details
+/// Upstreamness is the row sums of the Ghosh inverse, \eqn{U = G \mathbf{1}}, the average number of production stages
+/// between a sector and final use, and downstreamness is the column sums of the Leontief inverse,
+/// \eqn{D = \mathbf{1}' L}, the average number of stages between primary inputs and a sector
+/// \insertCite{miller_input-output_2009}{fio}.
+///
+/// The average propagation length from sector \eqn{i} to sector \eqn{j} is
+///
+/// \deqn{APL_{ij} = \frac{[L (L - I)]_{ij}}{l_{ij} - \delta_{ij}}}
+///
+/// for \eqn{l_{ij} - \delta_{ij} > 0}, and zero otherwise, where \eqn{\delta_{ij}} is the Kronecker delta.
+///
+/// Country upstreamness and downstreamness are averages of their sectors weighted by total production, and the
+/// average propagation length between two countries is the average of the lengths between their sectors weighted by
+/// \eqn{l_{ij} - \delta_{ij}}, over the pairs with a propagation path.
+///
+/// @return
+/// A list with vectors of upstreamness and downstreamness and a \eqn{n x n} matrix of average propagation lengths
+/// and, if `countries` are given, vectors of country upstreamness and downstreamness and a \eqn{r x r} matrix of
+/// average propagation lengths between countries (`NULL` otherwise).
+///
+/// @references
+/// \insertAllCited{}
+///
+/// @noRd
+fn compute_production_position(
+  leontief_factorization: ExternalPtr<Factorization>,
+  ghosh_factorization: ExternalPtr<Factorization>,
+  total_production: &[f64],
+  threads: usize,
+  #[default = "NULL"] labels: Nullable<Strings>,
+  #[default = "NULL"] countries: Nullable<Strings>
+) -> Result<List> {
+
+  // get dimensions
+  let n = leontief_factorization.n();
+  if ghosh_factorization.n() != n {
+    return Err("Leontief and Ghosh matrices must have the same dimensions.".into());
+  }
+  if total_production.len() != n {
+    return Err("total_production must have one element for each sector.".into());
+  }
+  // number of countries, zero without country aggregates
+  let n_countries = match &countries {
+    Nullable::NotNull(countries) if countries.is_empty() || n % countries.len() != 0 => {
+      return Err("Number of sectors must be a multiple of the number of countries.".into());
+    },
+    Nullable::NotNull(countries) => countries.len(),
+    Nullable::Null => 0,
+  };
+  let leontief_inverse = leontief_factorization.inverse_in_pool(threads)?;
+  let ghosh_inverse = ghosh_factorization.inverse_in_pool(threads)?;
+
+  let (upstreamness, downstreamness, propagation, country_propagation) = with_threads(threads, || {
+    // row sums of ghosh inverse and column sums of leontief inverse
+    let upstreamness: Vec<f64> = (0..n)
+      .into_par_iter()
+      .map(|row| (0..n).map(|col| ghosh_inverse[(row, col)]).sum())
+      .collect();
+    let downstreamness: Vec<f64> = (0..n)
+      .into_par_iter()
+      .map(|col| leontief_inverse.col(col).iter().sum())
+      .collect();
+
+    // L (L - I) = L^2 - L
+    let identity_matrix: Mat<f64> = Mat::identity(n, n);
+    let indirect = leontief_inverse - &identity_matrix;
+    let propagation = leontief_inverse * &indirect;
+
+    // country-pair averages
+    let country_propagation =
+      (n_countries > 0).then(|| country_propagation_length(&propagation, &indirect, n_countries));
+
+    // element-wise ratio, zero where there is no propagation path
+    let propagation = Mat::from_fn(n, n, |row, col| {
+      let weight = indirect[(row, col)];
+      if weight > TOLERANCE {
+        propagation[(row, col)] / weight
+      } else {
+        0.0
+      }
+    });
+
+    (upstreamness, downstreamness, propagation, country_propagation)
+  })?;
+
+  // output-weighted country averages
+  let (country_upstreamness, country_downstreamness, country_propagation): (Robj, Robj, Robj) =
+    match country_propagation {
+      Some(matrix) => (
+        set_names(country_weighted_mean(&upstreamness, total_production, n_countries), &countries)?.into(),
+        set_names(country_weighted_mean(&downstreamness, total_production, n_countries), &countries)?.into(),
+        set_dimnames(
+          RArray::new_matrix(n_countries, n_countries, |row, col| matrix[(row, col)]),
+          &countries,
+          &countries
+        )?
+        .into(),
+      ),
+      None => (().into(), ().into(), ().into()),
+    };
+
+  Ok(list!(
+    upstreamness = set_names(upstreamness, &labels)?,
+    downstreamness = set_names(downstreamness, &labels)?,
+    average_propagation_length = set_dimnames(
+      RArray::new_matrix(n, n, |row, col| propagation[(row, col)]),
+      &labels,
+      &labels
+    )?,
+    country_upstreamness = country_upstreamness,
+    country_downstreamness = country_downstreamness,
+    country_propagation_length = country_propagation
+  ))
+}
+
+// Macro to generate exports.
+// This ensures exported functions are registered with R.
+// See corresponding C code in `entrypoint.c`.
+extendr_module! {
+  mod position;
+  fn compute_production_position;
+}
@@ -0,0 +1,63 @@
+use extendr_api::prelude::*;
+use std::ops::Range;
+use std::os::raw::{c_int, c_void};
+
+use crate::parallel::thread_pool;
+
+/// Error message of a computation interrupted by the user, signaled as a condition of class `fio_interrupt` in R.
+pub(crate) const INTERRUPTED: &str = "fio_interrupt";
+
+// number of interrupt checks and progress updates along a computation
+const N_UPDATES: usize = 100;
+
+extern "C" {
+  fn R_CheckUserInterrupt();
+  fn R_ToplevelExec(fun: Option<unsafe extern "C" fn(*mut c_void)>, data: *mut c_void) -> c_int;
+}
+
+unsafe extern "C" fn check_user_interrupt(_data: *mut c_void) {
+  R_CheckUserInterrupt();
+}
+
+/// Checks whether the user asked R to interrupt the computation (e.g., by pressing ESC or Ctrl+C).
+///
+/// `R_CheckUserInterrupt()` jumps out of the current context if an interrupt is pending, so it runs within
+/// `R_ToplevelExec()`, which returns false instead of unwinding through Rust frames.
+fn interrupt_pending() -> bool {
+  single_threaded(|| unsafe { R_ToplevelExec(Some(check_user_interrupt), std::ptr::null_mut()) == 0 })
+}
+
+/// Runs a computation over `total` items (e.g., sectors) in chunks.
+///
+/// All chunks run in the same thread pool. Between chunks, back in R's main thread, the user interrupt flag is
+/// checked and the number of items done is reported to the progress callback, as `progress(done, total)`.
+pub(crate) fn run_in_chunks<T, F>(
+  total: usize,
+  threads: usize,
+  progress: &Nullable<Function>,
+  op: F,
+) -> Result<Vec<T>>
+where
+  T: Send,
+  F: Fn(Range<usize>) -> T + Sync,
+{
+  let pool = thread_pool(threads)?;
+  let chunk_size = total.div_ceil(N_UPDATES).max(pool.current_num_threads());
+  let mut results = Vec::with_capacity(total.div_ceil(chunk_size));
+
+  let mut done = 0;
+  while done < total {
+    let end = (done + chunk_size).min(total);
+    results.push(pool.install(|| op(done..end)));
+    done = end;
+
+    if interrupt_pending() {
+      return Err(INTERRUPTED.into());
+    }
+    if let NotNull(callback) = progress {
+      callback.call(pairlist!(done as f64, total as f64))?;
+    }
+  }
+
+  Ok(results)
+}
@@ -0,0 +1,218 @@
+use extendr_api::prelude::*;
+use faer::Mat;
+
+use crate::factorization::Factorization;
+use crate::labels::{set_dimnames, set_dimnames_3d};
+use crate::parallel::with_threads;
+
+/// Satellite requirements (intensities), \eqn{S \hat{x}^{-1}}, for a \eqn{k x n} satellite matrix in column-major
+/// order. Sectors with zero output have zero requirements.
+pub(crate) fn satellite_requirements(satellite: &[f64], total_production: &[f64]) -> Mat<f64> {
+  let n = total_production.len();
+  let k = satellite.len() / n;
+
+  Mat::from_fn(k, n, |row, col| {
+    if total_production[col] == 0.0 { 0.0 } else { satellite[col * k + row] / total_production[col] }
+  })
+}
+
+/// Checks the dimensions of a satellite matrix against the factorization of the Leontief matrix and gets the number
+/// of indicators.
+fn satellite_dimensions(satellite: &[f64], total_production: &[f64], n: usize) -> Result<usize> {
+  if n == 0 || total_production.len() != n || satellite.len() % n != 0 {
+    return Err("satellite must have one column for each sector.".into());
+  }
+  Ok(satellite.len() / n)
+}
+
+/// Converts a \eqn{k x n} matrix into an R matrix, labelled by indicator and sector.
+fn labelled_matrix(
+  matrix: &Mat<f64>,
+  indicator_labels: &Nullable<Strings>,
+  labels: &Nullable<Strings>
+) -> Result<RArray<f64, [usize;2]>> {
+  set_dimnames(
+    RArray::new_matrix(matrix.nrows(), matrix.ncols(), |row, col| matrix[(row, col)]),
+    indicator_labels,
+    labels
+  )
+}
+
+/// Satellite multipliers, \eqn{S L}, as a single dense matrix product.
+pub(crate) fn satellite_multipliers(requirements: &Mat<f64>, leontief_inverse: &Mat<f64>) -> Mat<f64> {
+  requirements * leontief_inverse
+}
+
+#[extendr]
+/// @description
+/// Computes multipliers for a matrix of satellite accounts at once.
+///
+/// @param satellite
+/// A \eqn{k x n} matrix of satellite accounts (e.g., emissions by gas, water, energy or employment by skill), one
+/// row for each indicator.
+/// @param total_production
+/// A \eqn{1 x n} vector of total production.
+/// @param leontief_factorization
+/// The factorization of the Leontief matrix, as returned by `compute_factorization()`.
+/// @param generators
+/// Whether to compute the generator matrices.
+/// @param threads
+/// Number of threads. 0 means the session-wide setting.
+/// @param indicator_labels
+/// Optional labels of the indicators, attached as row names.
+/// @param labels
+/// Optional labels of the sectors, attached as column names.
+///
+/// @details
+/// Requirements (direct multipliers) are the satellite accounts per unit of output, \eqn{R = S \hat{x}^{-1}}, and
+/// simple multipliers follow from a single matrix product with the Leontief inverse, \eqn{M = R L}
+/// \insertCite{miller_input-output_2009}{fio}. Indirect multipliers are the difference between simple and direct
+/// multipliers. Sectors with zero output have zero requirements.
+///
+/// The generator of indicator \eqn{h} is \eqn{\hat{r}_h L}, whose element \eqn{(i, j)} is the amount of the indicator
+/// generated in sector \eqn{i} to satisfy a unit of final demand for sector \eqn{j}
+/// \insertCite{vale_alise_2020}{fio}.
+///
+/// @return
+/// A list with \eqn{k x n} matrices of requirements, simple, direct and indirect multipliers and, if requested, a
+/// \eqn{k x n x n} array of generators (`NULL` otherwise).
+///
+/// @references
+/// \insertAllCited{}
+///
+/// @noRd
+fn compute_multiplier_satellite(
+  satellite: &[f64],
+  total_production: &[f64],
+  leontief_factorization: ExternalPtr<Factorization>,
+  generators: bool,
+  threads: usize,
+  #[default = "NULL"] indicator_labels: Nullable<Strings>,
+  #[default = "NULL"] labels: Nullable<Strings>
+) -> Result<List> {
+
+  // get dimensions
+  let n = leontief_factorization.n();
+  let k = satellite_dimensions(satellite, total_production, n)?;
+  let leontief_inverse = leontief_factorization.inverse_in_pool(threads)?;
+
+  // requirements and simple multipliers
+  let requirements = satellite_requirements(satellite, total_production);
+  let multipliers = with_threads(threads, || satellite_multipliers(&requirements, leontief_inverse))?;
+
+  let indirect = &multipliers - &requirements;
+
+  // generator slices, r_hi * l_ij
+  let generator: Robj = if generators {
+    set_dimnames_3d(
+      RArray::new_matrix3d(k, n, n, |h, i, j| requirements[(h, i)] * leontief_inverse[(i, j)]),
+      [&indicator_labels, &labels, &labels]
+    )?
+    .into()
+  } else {
+    ().into()
+  };
+
+  Ok(list!(
+    requirements = labelled_matrix(&requirements, &indicator_labels, &labels)?,
+    multiplier_simple = labelled_matrix(&multipliers, &indicator_labels, &labels)?,
+    multiplier_direct = labelled_matrix(&requirements, &indicator_labels, &labels)?,
+    multiplier_indirect = labelled_matrix(&indirect, &indicator_labels, &labels)?,
+    generator = generator
+  ))
+}
+
+#[allow(clippy::too_many_arguments)]
+#[extendr]
+/// @description
+/// Computes production-based and consumption-based footprints of a matrix of stressors.
+///
+/// @param satellite
+/// A \eqn{k x n} matrix of stressors (e.g., emissions, water or land use), one row for each stressor.
+/// @param total_production
+/// A \eqn{1 x n} vector of total production.
+/// @param leontief_factorization
+/// The factorization of the Leontief matrix, as returned by `compute_factorization()`.
+/// @param final_demand_matrix
+/// A \eqn{n x m} matrix of final demand, one column for each category.
+/// @param threads
+/// Number of threads. 0 means the session-wide setting.
+/// @param indicator_labels
+/// Optional labels of the stressors, attached as row names.
+/// @param labels
+/// Optional labels of the sectors, attached as column names.
+/// @param category_labels
+/// Optional labels of the final demand categories, attached as column names of the accounts by category.
+///
+/// @details
+/// Stressor intensities are \eqn{R = S \hat{x}^{-1}} and total intensities are \eqn{M = R L}
+/// \insertCite{miller_input-output_2009}{fio}. The production-based account is the stressor itself, attributed to
+/// the emitting sector, while the consumption-based account attributes the stressors embodied in the whole supply
+/// chain to the final product and to the final demand category that purchased it:
+///
+/// \deqn{C_{sector} = M \hat{f}, \quad C_{category} = M F}
+///
+/// where \eqn{f} is total final demand by sector and \eqn{F} is the final demand matrix. Sectors with zero output
+/// have zero intensities.
+///
+/// @return
+/// A list with \eqn{k x n} matrices of intensities, total intensities, production-based and consumption-based
+/// accounts by sector, and a \eqn{k x m} matrix of consumption-based accounts by final demand category.
+///
+/// @references
+/// \insertAllCited{}
+///
+/// @noRd
+fn compute_footprint(
+  satellite: &[f64],
+  total_production: &[f64],
+  leontief_factorization: ExternalPtr<Factorization>,
+  final_demand_matrix: RMatrix<f64>,
+  threads: usize,
+  #[default = "NULL"] indicator_labels: Nullable<Strings>,
+  #[default = "NULL"] labels: Nullable<Strings>,
+  #[default = "NULL"] category_labels: Nullable<Strings>
+) -> Result<List> {
+
+  // get dimensions
+  let n = leontief_factorization.n();
+  let k = satellite_dimensions(satellite, total_production, n)?;
+  if final_demand_matrix.nrows() != n {
+    return Err("final_demand_matrix must have one row for each sector.".into());
+  }
+  let m = final_demand_matrix.ncols();
+  let leontief_inverse = leontief_factorization.inverse_in_pool(threads)?;
+
+  // create faer matrix
+  let final_demand = Mat::from_fn(n, m, |row, col| final_demand_matrix[[row, col]]);
+
+  // intensities and total intensities
+  let requirements = satellite_requirements(satellite, total_production);
+  let (multipliers, by_category) = with_threads(threads, || {
+    let multipliers = satellite_multipliers(&requirements, leontief_inverse);
+    let by_category = &multipliers * &final_demand;
+    (multipliers, by_category)
+  })?;
+
+  // total final demand by sector
+  let final_demand_total: Vec<f64> = final_demand.row_iter().map(|row| row.iter().sum()).collect();
+  let production_based = Mat::from_fn(k, n, |row, col| satellite[col * k + row]);
+  let consumption_based = Mat::from_fn(k, n, |row, col| multipliers[(row, col)] * final_demand_total[col]);
+
+  Ok(list!(
+    intensities = labelled_matrix(&requirements, &indicator_labels, &labels)?,
+    total_intensities = labelled_matrix(&multipliers, &indicator_labels, &labels)?,
+    production_based = labelled_matrix(&production_based, &indicator_labels, &labels)?,
+    consumption_based = labelled_matrix(&consumption_based, &indicator_labels, &labels)?,
+    consumption_by_category = labelled_matrix(&by_category, &indicator_labels, &category_labels)?
+  ))
+}
+
+// Macro to generate exports.
+// This ensures exported functions are registered with R.
+// See corresponding C code in `entrypoint.c`.
+extendr_module! {
+  mod satellite;
+  fn compute_multiplier_satellite;
+  fn compute_footprint;
+}
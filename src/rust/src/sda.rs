@@ -0,0 +1,252 @@
+use extendr_api::prelude::*;
+use faer::Mat;
+use rayon::prelude::*;
+
+use crate::factorization::Factorization;
+use crate::labels::{fixed_labels, set_dimnames};
+use crate::parallel::with_threads;
+
+// number of factors: intensity, technology, final demand mix and final demand level
+const N_FACTORS: usize = 4;
+
+/// Logarithmic mean of two non-negative numbers, with limits \eqn{L(x, x) = x} and \eqn{L(0, y) = 0}.
+fn log_mean(a: f64, b: f64) -> f64 {
+  if a <= 0.0 || b <= 0.0 {
+    0.0
+  } else if (a - b).abs() <= f64::EPSILON * a.abs().max(b.abs()) {
+    a
+  } else {
+    (a - b) / (a.ln() - b.ln())
+  }
+}
+
+/// Additive LMDI contribution of each factor to the change of a term \eqn{v = x_1 x_2 ... x_k}.
+///
+/// When the term is zero in one period, the logarithmic mean vanishes while the log-ratio of the factors that are
+/// zero diverges. The limit of their product is the whole change of the term, which is split between those factors.
+fn lmdi_term(factors_0: &[f64; N_FACTORS], factors_1: &[f64; N_FACTORS]) -> [f64; N_FACTORS] {
+  let v0: f64 = factors_0.iter().product();
+  let v1: f64 = factors_1.iter().product();
+  let mut effects = [0.0; N_FACTORS];
+
+  if v0 > 0.0 && v1 > 0.0 {
+    let weight = log_mean(v1, v0);
+    for k in 0..N_FACTORS {
+      effects[k] = weight * (factors_1[k] / factors_0[k]).ln();
+    }
+  } else if v0 > 0.0 || v1 > 0.0 {
+    // factors that vanish in the period where the term is zero
+    let zero_factors = if v0 > 0.0 { factors_1 } else { factors_0 };
+    let mut vanishing: Vec<usize> = (0..N_FACTORS).filter(|&k| zero_factors[k] <= 0.0).collect();
+    if vanishing.is_empty() {
+      vanishing = (0..N_FACTORS).collect();
+    }
+    for &k in &vanishing {
+      effects[k] = (v1 - v0) / vanishing.len() as f64;
+    }
+  }
+
+  effects
+}
+
+/// All permutations of `0..k`.
+fn permutations(k: usize) -> Vec<Vec<usize>> {
+  if k == 0 {
+    return vec![vec![]];
+  }
+  let mut result = Vec::new();
+  for permutation in permutations(k - 1) {
+    for position in 0..=permutation.len() {
+      let mut new_permutation = permutation.clone();
+      new_permutation.insert(position, k - 1);
+      result.push(new_permutation);
+    }
+  }
+  result
+}
+
+#[allow(clippy::too_many_arguments)]
+#[extendr]
+/// @description
+/// Computes structural decomposition of the change in an indicator between two input-output tables.
+///
+/// @param leontief_factorization_0
+/// The factorization of the Leontief matrix of the base table, as returned by `compute_factorization()`.
+/// @param leontief_factorization_1
+/// The factorization of the Leontief matrix of the comparison table, as returned by `compute_factorization()`.
+/// @param final_demand_0
+/// A \eqn{n x 1} vector of total final demand of the base table.
+/// @param final_demand_1
+/// A \eqn{n x 1} vector of total final demand of the comparison table.
+/// @param intensity_0
+/// A \eqn{1 x n} vector of indicator intensities (indicator per unit of output) of the base table.
+/// @param intensity_1
+/// A \eqn{1 x n} vector of indicator intensities of the comparison table.
+/// @param method
+/// One of "polar", "dietzenbacher_los", "lmdi_additive" or "lmdi_multiplicative".
+/// @param threads
+/// Number of threads. 0 means the session-wide setting.
+/// @param labels
+/// Optional labels of the sectors, attached as row names.
+///
+/// @details
+/// The indicator by sector is written as the product of four factors:
+///
+/// \deqn{q = \hat{e} L s y}
+///
+/// where \eqn{e} are the intensities, \eqn{L} is the Leontief inverse (technology), \eqn{s} is the final demand
+/// mix (share of each sector in total final demand) and \eqn{y} is the final demand level.
+///
+/// The change \eqn{q_1 - q_0} is split into the contribution of each factor
+/// \insertCite{miller_input-output_2009}{fio}. Method "polar" averages the two polar decompositions, in which
+/// factors are changed one at a time from first to last and from last to first. Method "dietzenbacher_los"
+/// averages the decompositions for all 4! = 24 orderings of the factors, which gives an exact decomposition
+/// without residual. Methods "lmdi_additive" and "lmdi_multiplicative" use the logarithmic mean Divisia index
+/// applied to each term \eqn{e_i l_{ij} s_j y} of the indicator, which requires non-negative data. Terms that are
+/// zero in one of the tables are handled by the limits \eqn{L(x, x) = x} and \eqn{L(0, y) = 0}, so that additive
+/// effects still sum up to \eqn{q_1 - q_0}. In the multiplicative form, effects are ratios whose product equals
+/// \eqn{q_1 / q_0}, which requires the indicator to be positive in both tables.
+///
+/// @return
+/// A \eqn{n x 4} matrix with intensity, technology, final demand mix and final demand level effects by sector, with
+/// columns named after the effects.
+///
+/// @references
+/// \insertAllCited{}
+///
+/// @noRd
+fn compute_structural_decomposition(
+  leontief_factorization_0: ExternalPtr<Factorization>,
+  leontief_factorization_1: ExternalPtr<Factorization>,
+  final_demand_0: &[f64],
+  final_demand_1: &[f64],
+  intensity_0: &[f64],
+  intensity_1: &[f64],
+  method: &str,
+  threads: usize,
+  #[default = "NULL"] labels: Nullable<Strings>
+) -> Result<RArray<f64, [usize;2]>> {
+
+  // check method
+  if !matches!(method, "polar" | "dietzenbacher_los" | "lmdi_additive" | "lmdi_multiplicative") {
+    return Err(format!("Unknown structural decomposition method: {}", method).into());
+  }
+
+  // get dimensions
+  let n = leontief_factorization_0.n();
+
+  // check dimensions
+  if leontief_factorization_1.n() != n {
+    return Err("Both tables must have the same number of sectors.".into());
+  }
+  if [final_demand_0, final_demand_1, intensity_0, intensity_1].iter().any(|vector| vector.len() != n) {
+    return Err("final_demand and intensity must have one element for each sector.".into());
+  }
+
+  // check data for logarithmic mean Divisia index
+  let is_lmdi = matches!(method, "lmdi_additive" | "lmdi_multiplicative");
+  let has_negatives = [final_demand_0, final_demand_1, intensity_0, intensity_1]
+    .iter()
+    .any(|vector| vector.iter().any(|&value| value < 0.0));
+  if is_lmdi && has_negatives {
+    return Err("Logarithmic mean Divisia index requires non-negative final demand and intensities.".into());
+  }
+
+  let leontief_factorization_0: &Factorization = &leontief_factorization_0;
+  let leontief_factorization_1: &Factorization = &leontief_factorization_1;
+  let effects = with_threads(threads, || -> std::result::Result<Mat<f64>, String> {
+    // factors at both points in time
+    let leontief = [leontief_factorization_0.inverse(), leontief_factorization_1.inverse()];
+    let level = [final_demand_0.iter().sum::<f64>(), final_demand_1.iter().sum::<f64>()];
+    let mix = [(final_demand_0, level[0]), (final_demand_1, level[1])].map(|(final_demand, level)| {
+      final_demand.iter().map(|f| if level == 0.0 { 0.0 } else { f / level }).collect::<Vec<f64>>()
+    });
+    let intensity = [intensity_0, intensity_1];
+
+    match method {
+      "polar" | "dietzenbacher_los" => {
+        // indicator for every combination of factors in base (bit unset) or comparison (bit set) year
+        let states: Vec<Vec<f64>> = (0..(1 << N_FACTORS))
+          .into_par_iter()
+          .map(|state: usize| {
+            let e = intensity[state & 1];
+            let l = leontief[(state >> 1) & 1];
+            let s = &mix[(state >> 2) & 1];
+            let y = level[(state >> 3) & 1];
+            (0..n)
+              .map(|i| e[i] * (0..n).map(|j| l[(i, j)] * s[j]).sum::<f64>() * y)
+              .collect()
+          })
+          .collect();
+
+        // orderings in which factors are changed from base to comparison year
+        let orderings = if method == "polar" {
+          let forward: Vec<usize> = (0..N_FACTORS).collect();
+          let backward: Vec<usize> = (0..N_FACTORS).rev().collect();
+          vec![forward, backward]
+        } else {
+          permutations(N_FACTORS)
+        };
+
+        // average contribution of each factor across orderings
+        let mut effects = Mat::zeros(n, N_FACTORS);
+        for ordering in &orderings {
+          let mut state = 0;
+          for &factor in ordering {
+            let new_state = state | (1 << factor);
+            for i in 0..n {
+              effects[(i, factor)] += (states[new_state][i] - states[state][i]) / orderings.len() as f64;
+            }
+            state = new_state;
+          }
+        }
+        Ok(effects)
+      },
+      _ => {
+        let rows: Vec<std::result::Result<[f64; N_FACTORS], String>> = (0..n)
+          .into_par_iter()
+          .map(|i| {
+            // additive contribution of each term of the indicator for sector i
+            let mut effects = [0.0; N_FACTORS];
+            let (mut total_0, mut total_1) = (0.0, 0.0);
+            for j in 0..n {
+              let factors_0 = [intensity[0][i], leontief[0][(i, j)], mix[0][j], level[0]];
+              let factors_1 = [intensity[1][i], leontief[1][(i, j)], mix[1][j], level[1]];
+              total_0 += factors_0.iter().product::<f64>();
+              total_1 += factors_1.iter().product::<f64>();
+              let term = lmdi_term(&factors_0, &factors_1);
+              effects.iter_mut().zip(term).for_each(|(effect, value)| *effect += value);
+            }
+
+            // multiplicative effects from additive ones: ln(D_k) = delta_k / L(q_1, q_0)
+            if method == "lmdi_multiplicative" {
+              if total_0 <= 0.0 || total_1 <= 0.0 {
+                return Err("Multiplicative decomposition requires a positive indicator in both tables.".to_string());
+              }
+              let weight = log_mean(total_1, total_0);
+              effects.iter_mut().for_each(|effect| *effect = (*effect / weight).exp());
+            }
+            Ok(effects)
+          })
+          .collect();
+        let rows = rows.into_iter().collect::<std::result::Result<Vec<_>, String>>()?;
+        Ok(Mat::from_fn(n, N_FACTORS, |row, col| rows[row][col]))
+      },
+    }
+  })??;
+
+  // convert to R matrix
+  set_dimnames(
+    RArray::new_matrix(n, N_FACTORS, |row, col| effects[(row, col)]),
+    &labels,
+    &fixed_labels(&["intensity_effect", "technology_effect", "mix_effect", "level_effect"])
+  )
+}
+
+// Macro to generate exports.
+// This ensures exported functions are registered with R.
+// See corresponding C code in `entrypoint.c`.
+extendr_module! {
+  mod sda;
+  fn compute_structural_decomposition;
+}
@@ -0,0 +1,183 @@
+use extendr_api::prelude::*;
+use faer::Mat;
+use rayon::prelude::*;
+
+use crate::factorization::Factorization;
+use crate::parallel::with_threads;
+use crate::progress::run_in_chunks;
+
+// a supply chain path found by the tree search
+struct Path {
+  root: usize,
+  // sectors from final demand (root) to the sector where the indicator is generated
+  sectors: Vec<usize>,
+  value: f64,
+  share: f64,
+}
+
+#[allow(clippy::too_many_arguments)]
+#[extendr]
+/// @description
+/// Computes structural path analysis for a given value-added or satellite vector.
+///
+/// @param tech_coeff
+/// A \eqn{n x n} matrix of technical coefficients.
+/// @param leontief_factorization
+/// The factorization of the Leontief matrix, as returned by `compute_factorization()`.
+/// @param value_added_requirements
+/// The coefficients for a given value-added or satellite vector, as computed by `compute_requirements_value_added()`.
+/// @param final_demand
+/// A \eqn{n x 1} vector of final demand. A vector of ones gives paths of the multipliers.
+/// @param max_order
+/// Maximum order (number of intermediate transactions) of the paths.
+/// @param threshold
+/// Pruning threshold, as a share of the total effect of the final demand of the root sector.
+/// @param threads
+/// Number of threads. 0 means the session-wide setting.
+/// @param labels
+/// Optional labels of the sectors, naming them in the results instead of their 1-based indices.
+/// @param progress
+/// A function called with the number of root sectors done and the total number of sectors, or `NULL`.
+///
+/// @details
+/// The total effect of final demand on the indicator can be expanded in a power series of the technical
+/// coefficients matrix \insertCite{miller_input-output_2009}{fio}:
+///
+/// \deqn{e' L f = e' f + e' A f + e' A^2 f + \dots}
+///
+/// Each element of the series is a path, e.g., \eqn{e_i a_{ik} a_{kj} f_j} is the path of order 2 in which final
+/// demand for sector \eqn{j} requires inputs from sector \eqn{k}, which requires inputs from sector \eqn{i}, where
+/// the indicator is generated.
+///
+/// Paths are enumerated by a tree search starting from the final demand of each sector, which runs in parallel.
+/// A branch is pruned when the total effect still reachable from it, given by the path value up to the current
+/// sector times its total multiplier, falls below the threshold. Roots are searched in chunks, between which the
+/// computation can be interrupted by the user and progress is reported to the `progress` callback.
+///
+/// @return
+/// A list with the root sector, the path (sectors from the one generating the indicator to the final demand
+/// sector, joined by " -> "), the order, the value and the share of the path on the total multiplier effect of the root sector,
+/// sorted by decreasing absolute value.
+///
+/// @references
+/// \insertAllCited{}
+///
+/// @noRd
+fn compute_structural_paths(
+  tech_coeff: &[f64],
+  leontief_factorization: ExternalPtr<Factorization>,
+  value_added_requirements: &[f64],
+  final_demand: &[f64],
+  max_order: usize,
+  threshold: f64,
+  threads: usize,
+  #[default = "NULL"] labels: Nullable<Strings>,
+  #[default = "NULL"] progress: Nullable<Function>
+) -> Result<List> {
+
+  // get dimensions
+  let n = leontief_factorization.n();
+
+  // sector labels, or 1-based indices
+  let labels: Vec<String> = match labels {
+    NotNull(labels) if labels.len() != n => {
+      return Err(format!("Expected {} labels, got {}.", n, labels.len()).into());
+    },
+    NotNull(labels) => labels.iter().map(|label| label.to_string()).collect(),
+    Null => (1..=n).map(|sector| sector.to_string()).collect(),
+  };
+
+  // check dimensions
+  if tech_coeff.len() != n * n {
+    return Err("tech_coeff must be a n x n matrix, matching the factorization.".into());
+  }
+  if value_added_requirements.len() != n || final_demand.len() != n {
+    return Err("value_added_requirements and final_demand must have one element for each sector.".into());
+  }
+
+  // create faer matrix
+  let tech_coeff_matrix = Mat::from_fn(n, n, |row, col| tech_coeff[col * n + row]);
+
+  // total multipliers, m' = e' L, by solving (I - A)' m = e
+  let leontief_factorization: &Factorization = &leontief_factorization;
+  let multipliers = with_threads(threads, || leontief_factorization.solve(value_added_requirements, true))?;
+
+  // tree search from each root in parallel, by chunks of roots
+  let chunks = run_in_chunks(n, threads, &progress, |roots| {
+    roots
+      .into_par_iter()
+      .flat_map_iter(|root| {
+        let total = multipliers[root] * final_demand[root];
+        let cutoff = threshold * total.abs();
+        let mut found = Vec::new();
+        if total == 0.0 {
+          return found.into_iter();
+        }
+
+        // depth-first search with (current sector, product of coefficients times final demand, depth), sharing a
+        // single path that is cut back to the depth of each node when it is visited
+        let mut stack = vec![(root, final_demand[root], 0)];
+        let mut path: Vec<usize> = Vec::with_capacity(max_order + 1);
+        while let Some((sector, product, depth)) = stack.pop() {
+          path.truncate(depth);
+          path.push(sector);
+          let value = value_added_requirements[sector] * product;
+          if value != 0.0 && value.abs() >= cutoff {
+            found.push(Path {
+              root,
+              sectors: path.clone(),
+              value,
+              share: value / total,
+            });
+          }
+          if path.len() > max_order {
+            continue;
+          }
+          for supplier in 0..n {
+            let coefficient = tech_coeff_matrix[(supplier, sector)];
+            if coefficient == 0.0 {
+              continue;
+            }
+            let new_product = product * coefficient;
+            // prune branch if the effect reachable from it is below threshold
+            if (multipliers[supplier] * new_product).abs() < cutoff {
+              continue;
+            }
+            stack.push((supplier, new_product, depth + 1));
+          }
+        }
+        found.into_iter()
+      })
+      .collect::<Vec<Path>>()
+  })?;
+  let mut paths: Vec<Path> = chunks.into_iter().flatten().collect();
+
+  // rank paths
+  paths.sort_by(|a, b| b.value.abs().total_cmp(&a.value.abs()));
+
+  Ok(list!(
+    root = paths.iter().map(|path| labels[path.root].as_str()).collect::<Vec<&str>>(),
+    path = paths
+      .iter()
+      .map(|path| {
+        path.sectors
+          .iter()
+          .rev()
+          .map(|&sector| labels[sector].as_str())
+          .collect::<Vec<&str>>()
+          .join(" -> ")
+      })
+      .collect::<Vec<String>>(),
+    order = paths.iter().map(|path| (path.sectors.len() - 1) as i32).collect::<Vec<i32>>(),
+    value = paths.iter().map(|path| path.value).collect::<Vec<f64>>(),
+    share = paths.iter().map(|path| path.share).collect::<Vec<f64>>()
+  ))
+}
+
+// Macro to generate exports.
+// This ensures exported functions are registered with R.
+// See corresponding C code in `entrypoint.c`.
+extendr_module! {
+  mod spa;
+  fn compute_structural_paths;
+}
@@ -0,0 +1,576 @@
+use extendr_api::prelude::*;
+use faer::{
+  linalg::solvers::Solve,
+  sparse::{linalg::solvers::Lu, SparseColMat, Triplet},
+  Mat,
+};
+use rayon::prelude::*;
+use std::ops::Range;
+
+use crate::extraction::compute_extraction_differences;
+use crate::labels::{set_dimnames, set_names};
+use crate::leontief::{production_layers, production_layers_list};
+use crate::multipliers::inverse_production;
+use crate::parallel::with_threads;
+use crate::progress::run_in_chunks;
+
+// number of right-hand sides solved at once when columns of the inverse are needed
+const CHUNK_SIZE: usize = 256;
+
+/// Sparse \eqn{M}, the technical coefficients \eqn{z_{ij} / x_j} or, if `allocation` is true, the allocation
+/// coefficients \eqn{z_{ij} / x_i}. Only non-zero transactions are stored, and coefficients of sectors with zero
+/// output are set to zero. If `leontief` is true, \eqn{I - M} is built instead.
+fn sparse_coefficients_matrix(
+  intermediate_transactions: &[f64],
+  total_production: &[f64],
+  allocation: bool,
+  leontief: bool
+) -> Result<SparseColMat<usize, f64>> {
+  // get dimensions
+  let n = total_production.len();
+  if intermediate_transactions.len() != n * n {
+    return Err("intermediate_transactions must be a n x n matrix, matching total_production.".into());
+  }
+
+  // coefficients, or identity minus coefficients, duplicated entries are summed up
+  let sign = if leontief { -1.0 } else { 1.0 };
+  let mut triplets: Vec<Triplet<usize, usize, f64>> = if leontief {
+    (0..n).map(|i| Triplet::new(i, i, 1.0)).collect()
+  } else {
+    Vec::new()
+  };
+  for (index, &transaction) in intermediate_transactions.iter().enumerate() {
+    let (row, col) = (index % n, index / n);
+    let output = total_production[if allocation { row } else { col }];
+    if transaction != 0.0 && output != 0.0 {
+      triplets.push(Triplet::new(row, col, sign * transaction / output));
+    }
+  }
+
+  SparseColMat::<usize, f64>::try_new_from_triplets(n, n, &triplets)
+    .map_err(|_| Error::Other("Could not build sparse matrix.".into()))
+}
+
+/// Sparse LU decomposition of \eqn{I - M}, computed in a scoped thread pool.
+pub(crate) fn sparse_leontief_lu(
+  intermediate_transactions: &[f64],
+  total_production: &[f64],
+  allocation: bool,
+  threads: usize
+) -> Result<Lu<usize, f64>> {
+  let leontief_matrix = sparse_coefficients_matrix(intermediate_transactions, total_production, allocation, true)?;
+
+  with_threads(threads, || leontief_matrix.sp_lu())?
+    .map_err(|_| Error::Other("Sparse LU decomposition failed. Leontief matrix might be singular.".into()))
+}
+
+/// Runs a computation with the sparse LU decomposition of \eqn{I - A} in a scoped thread pool.
+pub(crate) fn with_sparse_lu<T, F>(
+  intermediate_transactions: &[f64],
+  total_production: &[f64],
+  threads: usize,
+  op: F
+) -> Result<T>
+where
+  T: Send,
+  F: FnOnce(&Lu<usize, f64>) -> T + Send,
+{
+  let lu = sparse_leontief_lu(intermediate_transactions, total_production, false, threads)?;
+
+  with_threads(threads, || op(&lu))
+}
+
+/// Solves \eqn{(I - M) x = b}, or \eqn{(I - M)' x = b} if `transpose` is true.
+pub(crate) fn sparse_solve(lu: &Lu<usize, f64>, rhs: &[f64], transpose: bool) -> Vec<f64> {
+  let mut solution = Mat::from_fn(rhs.len(), 1, |row, _| rhs[row]);
+  if transpose {
+    lu.solve_transpose_in_place(solution.as_mut());
+  } else {
+    lu.solve_in_place(solution.as_mut());
+  }
+  solution.col(0).iter().copied().collect()
+}
+
+// number of right-hand sides solved at once by each thread, up to CHUNK_SIZE
+fn rhs_chunk_size(n_rhs: usize) -> usize {
+  n_rhs.div_ceil(rayon::current_num_threads()).clamp(1, CHUNK_SIZE)
+}
+
+/// Columns of \eqn{(I - M)^{-1}}, solved in chunks so the full inverse is never formed.
+pub(crate) fn sparse_inverse_columns(lu: &Lu<usize, f64>, n: usize, columns: &[usize]) -> Mat<f64> {
+  let chunk_size = rhs_chunk_size(columns.len());
+  let chunks: Vec<Mat<f64>> = columns
+    .par_chunks(chunk_size)
+    .map(|chunk| {
+      let mut block = Mat::from_fn(n, chunk.len(), |row, col| if row == chunk[col] { 1.0 } else { 0.0 });
+      lu.solve_in_place(block.as_mut());
+      block
+    })
+    .collect();
+
+  Mat::from_fn(n, columns.len(), |row, col| chunks[col / chunk_size][(row, col % chunk_size)])
+}
+
+/// Elements of the diagonal of \eqn{(I - M)^{-1}} at the given indices.
+fn sparse_inverse_diagonal(lu: &Lu<usize, f64>, n: usize, indices: Range<usize>) -> Vec<f64> {
+  let chunk_size = rhs_chunk_size(indices.len());
+  indices
+    .collect::<Vec<usize>>()
+    .par_chunks(chunk_size)
+    .flat_map_iter(|chunk| {
+      let mut block = Mat::from_fn(n, chunk.len(), |row, col| if row == chunk[col] { 1.0 } else { 0.0 });
+      lu.solve_in_place(block.as_mut());
+      chunk
+        .iter()
+        .enumerate()
+        .map(|(col, &row)| block[(row, col)])
+        .collect::<Vec<f64>>()
+    })
+    .collect()
+}
+
+#[extendr]
+/// @description
+/// Computes selected columns of the Leontief inverse from a sparse matrix of technical coefficients.
+///
+/// @param intermediate_transactions
+/// A \eqn{n x n} matrix of intermediate transactions.
+/// @param total_production
+/// A \eqn{1 x n} vector of total production.
+/// @param columns
+/// 1-based indices of the columns of the Leontief inverse to be computed.
+/// @param threads
+/// Number of threads. 0 means the session-wide setting.
+/// @param progress
+/// A function called with the number of columns done and the total number of columns, or `NULL`.
+/// @param labels
+/// Optional labels of the sectors, attached as row names and, for the requested columns, as column names.
+///
+/// @details
+/// The Leontief matrix \eqn{I - A} is built from the non-zero intermediate transactions, stored in compressed sparse
+/// column (CSC) format and decomposed through sparse LU. Requested columns of \eqn{L = (I - A)^{-1}} are then
+/// obtained by solving \eqn{(I - A) l_j = e_j}, so neither the dense technical coefficients nor the dense
+/// \eqn{n x n} inverse are formed. Columns are solved in chunks, between which the computation can be
+/// interrupted by the user and progress is reported to the `progress` callback.
+///
+/// @return
+/// A \eqn{n x k} matrix with the requested columns of the Leontief inverse.
+///
+/// @noRd
+fn compute_leontief_columns_sparse(
+  intermediate_transactions: &[f64],
+  total_production: &[f64],
+  columns: &[i32],
+  threads: usize,
+  #[default = "NULL"] progress: Nullable<Function>,
+  #[default = "NULL"] labels: Nullable<Strings>
+) -> Result<RArray<f64, [usize;2]>> {
+
+  // get dimensions
+  let n = total_production.len();
+
+  // check requested columns
+  if columns.iter().any(|&col| col < 1 || col as usize > n) {
+    return Err("columns out of bounds.".into());
+  }
+  let columns: Vec<usize> = columns.iter().map(|&col| col as usize - 1).collect();
+
+  // decompose Leontief matrix
+  let lu = sparse_leontief_lu(intermediate_transactions, total_production, false, threads)?;
+
+  // solve for requested columns, by chunks
+  let chunks = run_in_chunks(columns.len(), threads, &progress, |range| {
+    sparse_inverse_columns(&lu, n, &columns[range])
+  })?;
+  let mut leontief_columns = Mat::zeros(n, columns.len());
+  let mut col = 0;
+  for chunk in chunks {
+    leontief_columns.subcols_mut(col, chunk.ncols()).copy_from(&chunk);
+    col += chunk.ncols();
+  }
+
+  // labels of the requested columns
+  let column_labels = match &labels {
+    NotNull(labels) if labels.len() == n => NotNull(columns.iter().map(|&col| labels.elt(col)).collect()),
+    _ => Null,
+  };
+
+  // convert to R matrix
+  set_dimnames(
+    RArray::new_matrix(n, columns.len(), |row, col| leontief_columns[(row, col)]),
+    &labels,
+    &column_labels
+  )
+}
+
+#[extendr]
+/// @description
+/// Computes multipliers from a sparse matrix of technical coefficients.
+///
+/// @param intermediate_transactions
+/// A \eqn{n x n} matrix of intermediate transactions.
+/// @param total_production
+/// A \eqn{1 x n} vector of total production.
+/// @param requirements
+/// A \eqn{1 x n} vector of requirements. A vector of ones gives output multipliers, while value-added requirements,
+/// as computed by `compute_requirements_value_added()`, give value-added multipliers.
+/// @param threads
+/// Number of threads. 0 means the session-wide setting.
+/// @param labels
+/// Optional labels of the sectors, attached as names.
+///
+/// @details
+/// Multipliers \eqn{m' = r' L} are computed by solving \eqn{(I - A)' m = r} with the sparse LU decomposition of the
+/// Leontief matrix, without forming the Leontief inverse.
+///
+/// @return
+/// A \eqn{1 x n} vector of multipliers.
+///
+/// @noRd
+fn compute_multiplier_sparse(
+  intermediate_transactions: &[f64],
+  total_production: &[f64],
+  requirements: &[f64],
+  threads: usize,
+  #[default = "NULL"] labels: Nullable<Strings>
+) -> Result<Doubles> {
+
+  if requirements.len() != total_production.len() {
+    return Err("requirements must have one element for each sector.".into());
+  }
+  let multipliers = with_sparse_lu(intermediate_transactions, total_production, threads, |lu| {
+    sparse_solve(lu, requirements, true)
+  })?;
+  set_names(multipliers, &labels)
+}
+
+#[extendr]
+/// @description
+/// Computes direct output multipliers from a sparse matrix of technical coefficients.
+///
+/// @param intermediate_transactions
+/// A \eqn{n x n} matrix of intermediate transactions.
+/// @param total_production
+/// A \eqn{1 x n} vector of total production.
+/// @param labels
+/// Optional labels of the sectors, attached as names.
+///
+/// @details
+/// Direct multipliers are the column sums of the technical coefficients, \eqn{\sum_i z_{ij} / x_j}, computed from
+/// the intermediate transactions without forming the matrix of technical coefficients. Sectors with zero output
+/// have zero direct multipliers.
+///
+/// @return
+/// A \eqn{1 x n} vector of direct output multipliers.
+///
+/// @noRd
+fn compute_multiplier_direct_sparse(
+  intermediate_transactions: &[f64],
+  total_production: &[f64],
+  #[default = "NULL"] labels: Nullable<Strings>
+) -> Result<Doubles> {
+
+  // get dimensions
+  let n = total_production.len();
+  if intermediate_transactions.len() != n * n {
+    return Err("intermediate_transactions must be a n x n matrix, matching total_production.".into());
+  }
+
+  let multipliers: Vec<f64> = intermediate_transactions
+    .par_chunks(n.max(1))
+    .zip(total_production.par_iter())
+    .map(|(column, &output)| if output == 0.0 { 0.0 } else { column.iter().sum::<f64>() / output })
+    .collect();
+  set_names(multipliers, &labels)
+}
+
+#[extendr]
+/// @description
+/// Computes output-to-output multipliers from a sparse matrix of technical coefficients.
+///
+/// @param intermediate_transactions
+/// A \eqn{n x n} matrix of intermediate transactions.
+/// @param total_production
+/// A \eqn{1 x n} vector of total production.
+/// @param threads
+/// Number of threads. 0 means the session-wide setting.
+/// @param labels
+/// Optional labels of the sectors, attached as names.
+///
+/// @details
+/// Output-to-output multipliers, the column sums of \eqn{\hat{x}^{-1} L \hat{x}}, are computed by solving
+/// \eqn{(I - A)' m = \hat{x}^{-1} \iota} and scaling by output, without forming the Leontief inverse. Sectors
+/// without output neither add to nor receive output-to-output effects.
+///
+/// @return
+/// A \eqn{1 x n} vector of output-to-output multipliers.
+///
+/// @noRd
+fn compute_multiplier_output_to_output_sparse(
+  intermediate_transactions: &[f64],
+  total_production: &[f64],
+  threads: usize,
+  #[default = "NULL"] labels: Nullable<Strings>
+) -> Result<Doubles> {
+
+  let requirements = inverse_production(total_production);
+  let multipliers = with_sparse_lu(intermediate_transactions, total_production, threads, |lu| {
+    sparse_solve(lu, &requirements, true)
+  })?;
+  let output_effects: Vec<f64> = multipliers
+    .iter()
+    .zip(total_production)
+    .map(|(multiplier, production)| multiplier * production)
+    .collect();
+  set_names(output_effects, &labels)
+}
+
+#[extendr]
+/// @description
+/// Computes the output required by a final demand vector from a sparse matrix of technical coefficients.
+///
+/// @param intermediate_transactions
+/// A \eqn{n x n} matrix of intermediate transactions.
+/// @param total_production
+/// A \eqn{1 x n} vector of total production.
+/// @param final_demand
+/// A \eqn{n x 1} vector of final demand.
+/// @param threads
+/// Number of threads. 0 means the session-wide setting.
+/// @param labels
+/// Optional labels of the sectors, attached as names.
+///
+/// @details
+/// Output \eqn{x = L f} is computed by solving \eqn{(I - A) x = f} with the sparse LU decomposition of the
+/// Leontief matrix, without forming the Leontief inverse.
+///
+/// @return
+/// A \eqn{n x 1} vector of output.
+///
+/// @noRd
+fn compute_impact_sparse(
+  intermediate_transactions: &[f64],
+  total_production: &[f64],
+  final_demand: &[f64],
+  threads: usize,
+  #[default = "NULL"] labels: Nullable<Strings>
+) -> Result<Doubles> {
+
+  if final_demand.len() != total_production.len() {
+    return Err("final_demand must have one element for each sector.".into());
+  }
+  let output = with_sparse_lu(intermediate_transactions, total_production, threads, |lu| {
+    sparse_solve(lu, final_demand, false)
+  })?;
+  set_names(output, &labels)
+}
+
+#[extendr]
+/// @description
+/// Computes backward linkage extraction from a sparse matrix of technical coefficients.
+///
+/// @param intermediate_transactions
+/// A \eqn{n x n} matrix of intermediate transactions.
+/// @param final_demand
+/// A \eqn{n x 1} vector of total final demand.
+/// @param total_production
+/// A \eqn{1 x n} vector of total production.
+/// @param threads
+/// Number of threads. 0 means the session-wide setting.
+/// @param progress
+/// A function called with the number of sectors done and the total number of sectors, or `NULL`.
+/// @param labels
+/// Optional labels of the sectors, attached as row names.
+///
+/// @details
+/// Extracting the demand structure of sector \eqn{j} is a rank-one change of the Leontief matrix, so total output
+/// after extraction follows from the Sherman-Morrison formula:
+///
+/// \deqn{1' x_{(-j)} = 1' x - \frac{(m_j - 1) x_j}{l_{jj}}}
+///
+/// where \eqn{x = L f}, \eqn{m_j} is the output multiplier and \eqn{l_{jj}} is the diagonal of the Leontief
+/// inverse \insertCite{miller_input-output_2009}{fio}. Only solves with the sparse LU decomposition are needed.
+/// The diagonal is solved in chunks, between which the computation can be interrupted by the user and progress is
+/// reported to the `progress` callback.
+///
+/// @return
+/// A \eqn{n x 2} matrix of absolute and relative differences in total output.
+///
+/// @references
+/// \insertAllCited{}
+///
+/// @noRd
+fn compute_extraction_backward_sparse(
+  intermediate_transactions: &[f64],
+  final_demand: &[f64],
+  total_production: &[f64],
+  threads: usize,
+  #[default = "NULL"] progress: Nullable<Function>,
+  #[default = "NULL"] labels: Nullable<Strings>
+) -> Result<RArray<f64, [usize;2]>> {
+
+  // get dimensions
+  let n = total_production.len();
+  if final_demand.len() != n {
+    return Err("final_demand must have one element for each sector.".into());
+  }
+
+  let lu = sparse_leontief_lu(intermediate_transactions, total_production, false, threads)?;
+
+  // output, output multipliers and diagonal of the Leontief inverse
+  let (output, multipliers) = with_threads(threads, || {
+    (sparse_solve(&lu, final_demand, false), sparse_solve(&lu, &vec![1.0; n], true))
+  })?;
+  let diagonal = run_in_chunks(n, threads, &progress, |range| sparse_inverse_diagonal(&lu, n, range))?.concat();
+
+  // diff in output after extracting each sector demand structure
+  compute_extraction_differences(
+    &output,
+    &multipliers,
+    &diagonal,
+    total_production,
+    &labels,
+    &["backward_absolute", "backward_relative"]
+  )
+}
+
+#[extendr]
+/// @description
+/// Computes forward linkage extraction from a sparse matrix of technical or allocation coefficients, built from the
+/// intermediate transactions.
+///
+/// @param intermediate_transactions
+/// A \eqn{n x n} matrix of intermediate transactions.
+/// @param allocation
+/// Whether the base matrix is built from allocation (Ghosh) rather than technical (Leontief) coefficients.
+/// @param value_added
+/// A \eqn{1 x n} vector of total value-added.
+/// @param total_production
+/// A \eqn{1 x n} vector of total production.
+/// @param threads
+/// Number of threads. 0 means the session-wide setting.
+/// @param progress
+/// A function called with the number of sectors done and the total number of sectors, or `NULL`.
+/// @param labels
+/// Optional labels of the sectors, attached as row names.
+///
+/// @details
+/// Extracting the supply structure of sector \eqn{i} is a rank-one change of the base (Ghosh or Leontief) matrix,
+/// so total output after extraction follows from the Sherman-Morrison formula:
+///
+/// \deqn{x_{(-i)}' 1 = x' 1 - \frac{x_i (g_i - 1)}{g_{ii}}}
+///
+/// where \eqn{x' = v' G}, \eqn{g_i} is the row sum and \eqn{g_{ii}} is the diagonal of the base inverse
+/// \insertCite{miller_input-output_2009}{fio}. Only solves with the sparse LU decomposition are needed. The diagonal
+/// is solved in chunks, between which the computation can be interrupted by the user and progress is reported to the
+/// `progress` callback.
+///
+/// @return
+/// A \eqn{n x 2} matrix of absolute and relative differences in total output.
+///
+/// @references
+/// \insertAllCited{}
+///
+/// @noRd
+fn compute_extraction_forward_sparse(
+  intermediate_transactions: &[f64],
+  allocation: bool,
+  value_added: &[f64],
+  total_production: &[f64],
+  threads: usize,
+  #[default = "NULL"] progress: Nullable<Function>,
+  #[default = "NULL"] labels: Nullable<Strings>
+) -> Result<RArray<f64, [usize;2]>> {
+
+  // get dimensions
+  let n = total_production.len();
+  if value_added.len() != n {
+    return Err("value_added must have one element for each sector.".into());
+  }
+
+  let lu = sparse_leontief_lu(intermediate_transactions, total_production, allocation, threads)?;
+
+  // output, row sums and diagonal of the base inverse
+  let (output, row_sums) = with_threads(threads, || {
+    (sparse_solve(&lu, value_added, true), sparse_solve(&lu, &vec![1.0; n], false))
+  })?;
+  let diagonal = run_in_chunks(n, threads, &progress, |range| sparse_inverse_diagonal(&lu, n, range))?.concat();
+
+  // diff in output after extracting each sector supply structure
+  compute_extraction_differences(
+    &output,
+    &row_sums,
+    &diagonal,
+    total_production,
+    &labels,
+    &["forward_absolute", "forward_relative"]
+  )
+}
+
+#[extendr]
+/// @description
+/// Computes production layers of a final demand vector from a sparse matrix of technical coefficients.
+///
+/// @param intermediate_transactions
+/// A \eqn{n x n} matrix of intermediate transactions.
+/// @param total_production
+/// A \eqn{1 x n} vector of total production.
+/// @param final_demand
+/// A \eqn{n x 1} vector of final demand.
+/// @param max_order
+/// Maximum number of rounds of intermediate demand.
+/// @param tolerance
+/// Convergence criterion. Iterations stop when the residual falls below this share of total output.
+/// @param threads
+/// Number of threads. 0 means the session-wide setting.
+/// @param labels
+/// Optional labels of the sectors, attached as row names.
+///
+/// @details
+/// Same as `compute_production_layers()`, but the technical coefficients of the non-zero intermediate transactions
+/// are stored in compressed sparse column (CSC) format, so each round is a sparse matrix-vector product and the
+/// dense matrix of technical coefficients is never formed.
+///
+/// @return
+/// A list with a \eqn{n x (K + 1)} matrix of production layers, with columns named after rounds, the residual and
+/// whether the series converged.
+///
+/// @noRd
+fn compute_production_layers_sparse(
+  intermediate_transactions: &[f64],
+  total_production: &[f64],
+  final_demand: &[f64],
+  max_order: usize,
+  tolerance: f64,
+  threads: usize,
+  #[default = "NULL"] labels: Nullable<Strings>
+) -> Result<List> {
+
+  // get dimensions
+  let n = total_production.len();
+  if final_demand.len() != n {
+    return Err("final_demand must have one element for each sector.".into());
+  }
+
+  let tech_coeff_matrix = sparse_coefficients_matrix(intermediate_transactions, total_production, false, false)?;
+  let (layers, residual, converged) = with_threads(threads, || {
+    production_layers(|layer| &tech_coeff_matrix * layer, final_demand, max_order, tolerance)
+  })?;
+
+  production_layers_list(&layers, residual, converged, &labels)
+}
+
+// Macro to generate exports.
+// This ensures exported functions are registered with R.
+// See corresponding C code in `entrypoint.c`.
+extendr_module! {
+  mod sparse;
+  fn compute_leontief_columns_sparse;
+  fn compute_multiplier_sparse;
+  fn compute_multiplier_direct_sparse;
+  fn compute_multiplier_output_to_output_sparse;
+  fn compute_impact_sparse;
+  fn compute_extraction_backward_sparse;
+  fn compute_extraction_forward_sparse;
+  fn compute_production_layers_sparse;
+}
@@ -0,0 +1,379 @@
+use extendr_api::prelude::*;
+use faer::{linalg::solvers::Solve, Mat};
+
+use crate::labels::{set_dimnames, set_names};
+use crate::parallel::with_threads;
+
+/// Share of a value in a total, zero if the total is zero.
+fn share(value: f64, total: f64) -> f64 {
+  if total == 0.0 { 0.0 } else { value / total }
+}
+
+/// Supply-use coefficients from make (industry x product) and use (product x industry) matrices: market shares,
+/// \eqn{D = V \hat{q}^{-1}}, product mix, \eqn{C = V' \hat{g}^{-1}}, and use coefficients, \eqn{B = U \hat{g}^{-1}}. Products or industries without output have zero coefficients.
+struct SupplyUse {
+  make: Mat<f64>,
+  use_table: Mat<f64>,
+  product_output: Vec<f64>,
+  industry_output: Vec<f64>,
+}
+
+impl SupplyUse {
+  fn new(make_matrix: &RMatrix<f64>, use_matrix: &RMatrix<f64>) -> Result<Self> {
+    let (m, p) = (make_matrix.nrows(), make_matrix.ncols());
+    if use_matrix.nrows() != p || use_matrix.ncols() != m {
+      return Err("use_matrix must have one row for each product and one column for each industry.".into());
+    }
+
+    let make = Mat::from_fn(m, p, |row, col| make_matrix[[row, col]]);
+    let use_table = Mat::from_fn(p, m, |row, col| use_matrix[[row, col]]);
+    let product_output: Vec<f64> = (0..p).map(|col| make.col(col).iter().sum()).collect();
+    let industry_output: Vec<f64> = (0..m).map(|row| make.row(row).iter().sum()).collect();
+
+    Ok(SupplyUse { make, use_table, product_output, industry_output })
+  }
+
+  fn market_share(&self) -> Mat<f64> {
+    Mat::from_fn(self.make.nrows(), self.make.ncols(), |i, j| share(self.make[(i, j)], self.product_output[j]))
+  }
+
+  fn product_mix(&self) -> Mat<f64> {
+    Mat::from_fn(self.make.ncols(), self.make.nrows(), |j, i| share(self.make[(i, j)], self.industry_output[i]))
+  }
+
+  fn use_coefficients(&self) -> Mat<f64> {
+    let (p, m) = (self.use_table.nrows(), self.use_table.ncols());
+    Mat::from_fn(p, m, |j, i| share(self.use_table[(j, i)], self.industry_output[i]))
+  }
+}
+
+#[extendr]
+/// @description
+/// Computes market shares, product mix and use coefficients of a supply-use table.
+///
+/// @param make_matrix
+/// A \eqn{m x p} make matrix, of industries by products.
+/// @param use_matrix
+/// A \eqn{p x m} use matrix, of products by industries.
+/// @param industries
+/// Optional labels of the industries, attached as names.
+/// @param products
+/// Optional labels of the products, attached as names.
+///
+/// @details
+/// With product output \eqn{q} and industry output \eqn{g}, market shares are \eqn{D = V \hat{q}^{-1}}, the share of
+/// each product made by each industry, product mix is \eqn{C = V' \hat{g}^{-1}}, the share of each product in the
+/// output of each industry, and use coefficients are \eqn{B = U \hat{g}^{-1}}
+/// \insertCite{miller_input-output_2009}{fio}. Products or industries without output have zero coefficients.
+///
+/// @return
+/// A list with vectors of product and industry output and matrices of market shares, product mix and use
+/// coefficients.
+///
+/// @references
+/// \insertAllCited{}
+///
+/// @noRd
+fn compute_sut_coefficients(
+  make_matrix: RMatrix<f64>,
+  use_matrix: RMatrix<f64>,
+  #[default = "NULL"] industries: Nullable<Strings>,
+  #[default = "NULL"] products: Nullable<Strings>
+) -> Result<List> {
+  let table = SupplyUse::new(&make_matrix, &use_matrix)?;
+  let (m, p) = (table.make.nrows(), table.make.ncols());
+
+  let market_share = table.market_share();
+  let product_mix = table.product_mix();
+  let use_coefficients = table.use_coefficients();
+
+  Ok(list!(
+    product_output = set_names(table.product_output.clone(), &products)?,
+    industry_output = set_names(table.industry_output.clone(), &industries)?,
+    market_share = set_dimnames(
+      RArray::new_matrix(m, p, |row, col| market_share[(row, col)]),
+      &industries,
+      &products
+    )?,
+    product_mix = set_dimnames(
+      RArray::new_matrix(p, m, |row, col| product_mix[(row, col)]),
+      &products,
+      &industries
+    )?,
+    use_coefficients = set_dimnames(
+      RArray::new_matrix(p, m, |row, col| use_coefficients[(row, col)]),
+      &products,
+      &industries
+    )?
+  ))
+}
+
+#[extendr]
+/// @description
+/// Computes total requirements (Leontief) matrices of a supply-use table under the industry-based technology
+/// assumption.
+///
+/// @param make_matrix
+/// A \eqn{m x p} make matrix, of industries by products.
+/// @param use_matrix
+/// A \eqn{p x m} use matrix, of products by industries.
+/// @param threads
+/// Number of threads. 0 means the session-wide setting.
+/// @param industries
+/// Optional labels of the industries, attached as names.
+/// @param products
+/// Optional labels of the products, attached as names.
+///
+/// @details
+/// With market shares \eqn{D} and use coefficients \eqn{B}, total requirements are
+/// \insertCite{miller_input-output_2009}{fio}:
+///
+/// \deqn{(I - B D)^{-1}, \quad (I - D B)^{-1}, \quad D (I - B D)^{-1}, \quad B (I - D B)^{-1}}
+///
+/// for commodity-by-commodity, industry-by-industry, industry-by-commodity and commodity-by-industry requirements,
+/// respectively.
+///
+/// @return
+/// A list with \eqn{p x p} commodity-by-commodity, \eqn{m x m} industry-by-industry, \eqn{m x p}
+/// industry-by-commodity and \eqn{p x m} commodity-by-industry total requirements matrices.
+///
+/// @references
+/// \insertAllCited{}
+///
+/// @noRd
+fn compute_sut_multipliers(
+  make_matrix: RMatrix<f64>,
+  use_matrix: RMatrix<f64>,
+  threads: usize,
+  #[default = "NULL"] industries: Nullable<Strings>,
+  #[default = "NULL"] products: Nullable<Strings>
+) -> Result<List> {
+  let table = SupplyUse::new(&make_matrix, &use_matrix)?;
+  let (m, p) = (table.make.nrows(), table.make.ncols());
+
+  let (commodity, industry, industry_commodity, commodity_industry) = with_threads(threads, || {
+    let market_share = table.market_share();
+    let use_coefficients = table.use_coefficients();
+
+    // commodity-by-commodity, (I - B D)^{-1}
+    let identity_products: Mat<f64> = Mat::identity(p, p);
+    let commodity = (&identity_products - &use_coefficients * &market_share)
+      .partial_piv_lu()
+      .solve(&identity_products);
+
+    // industry-by-industry, (I - D B)^{-1}
+    let identity_industries: Mat<f64> = Mat::identity(m, m);
+    let industry = (&identity_industries - &market_share * &use_coefficients)
+      .partial_piv_lu()
+      .solve(&identity_industries);
+
+    // industry-by-commodity, D (I - B D)^{-1}
+    let industry_commodity = &market_share * &commodity;
+
+    // commodity-by-industry, B (I - D B)^{-1}
+    let commodity_industry = &use_coefficients * &industry;
+
+    (commodity, industry, industry_commodity, commodity_industry)
+  })?;
+
+  Ok(list!(
+    commodity_by_commodity = set_dimnames(
+      RArray::new_matrix(p, p, |row, col| commodity[(row, col)]),
+      &products,
+      &products
+    )?,
+    industry_by_industry = set_dimnames(
+      RArray::new_matrix(m, m, |row, col| industry[(row, col)]),
+      &industries,
+      &industries
+    )?,
+    industry_by_commodity = set_dimnames(
+      RArray::new_matrix(m, p, |row, col| industry_commodity[(row, col)]),
+      &industries,
+      &products
+    )?,
+    commodity_by_industry = set_dimnames(
+      RArray::new_matrix(p, m, |row, col| commodity_industry[(row, col)]),
+      &products,
+      &industries
+    )?
+  ))
+}
+
+#[allow(clippy::too_many_arguments)]
+#[extendr]
+/// @description
+/// Converts a supply-use table into a symmetric input-output table.
+///
+/// @param make_matrix
+/// A \eqn{m x p} make matrix, of industries by products.
+/// @param use_matrix
+/// A \eqn{p x m} use matrix, of products by industries.
+/// @param final_demand
+/// A \eqn{p x k} matrix of final demand, of products by final demand categories.
+/// @param value_added
+/// A \eqn{h x m} matrix of value added, of components by industries.
+/// @param model
+/// One of Eurostat models "A", "B", "C" or "D".
+/// @param remove_negatives
+/// Whether to set negative intermediate transactions to zero.
+/// @param threads
+/// Number of threads. 0 means the session-wide setting.
+/// @param industries
+/// Optional labels of the industries, naming the sectors of industry-by-industry tables.
+/// @param products
+/// Optional labels of the products, naming the sectors of product-by-product tables.
+/// @param category_labels
+/// Optional labels of the final demand categories.
+/// @param component_labels
+/// Optional labels of the value added components.
+///
+/// @details
+/// The symmetric table follows from a transformation matrix \eqn{T} of industries by products:
+///
+/// * Model A, product-by-product under the product technology assumption, \eqn{T = (V')^{-1} \hat{q}};
+/// * Model B, product-by-product under the industry technology assumption, \eqn{T = \hat{g}^{-1} V};
+/// * Model C, industry-by-industry under the fixed industry sales structure assumption, \eqn{T = \hat{g} (V')^{-1}};
+/// * Model D, industry-by-industry under the fixed product sales structure assumption, \eqn{T = V \hat{q}^{-1}}.
+///
+/// Product-by-product tables are \eqn{Z = U T}, with value added \eqn{W T}, and industry-by-industry tables are
+/// \eqn{Z = T U}, with final demand \eqn{T Y}. Models A and C require a square make matrix and may yield negative
+/// transactions, value added (model A) or final demand (model C). Negative transactions can be removed, in which
+/// case the remaining transactions of each column are scaled so that column totals are kept (or set to zero, if the
+/// total is negative).
+///
+/// @return
+/// A list with the intermediate transactions, total production, final demand and value added of the symmetric
+/// table, and the number of negative intermediate transactions and, for models A and C, of negative entries of the
+/// transformed value added or final demand.
+///
+/// @noRd
+fn compute_symmetric_table(
+  make_matrix: RMatrix<f64>,
+  use_matrix: RMatrix<f64>,
+  final_demand: RMatrix<f64>,
+  value_added: RMatrix<f64>,
+  model: &str,
+  remove_negatives: bool,
+  threads: usize,
+  #[default = "NULL"] industries: Nullable<Strings>,
+  #[default = "NULL"] products: Nullable<Strings>,
+  #[default = "NULL"] category_labels: Nullable<Strings>,
+  #[default = "NULL"] component_labels: Nullable<Strings>
+) -> Result<List> {
+  let table = SupplyUse::new(&make_matrix, &use_matrix)?;
+  let (m, p) = (table.make.nrows(), table.make.ncols());
+
+  // check dimensions
+  if final_demand.nrows() != p {
+    return Err("final_demand must have one row for each product.".into());
+  }
+  if value_added.ncols() != m {
+    return Err("value_added must have one column for each industry.".into());
+  }
+  if matches!(model, "A" | "C") && m != p {
+    return Err("Models A and C require the same number of products and industries.".into());
+  }
+  if !matches!(model, "A" | "B" | "C" | "D") {
+    return Err("model must be one of 'A', 'B', 'C' or 'D'.".into());
+  }
+
+  // create faer matrices
+  let final_demand_matrix = Mat::from_fn(p, final_demand.ncols(), |row, col| final_demand[[row, col]]);
+  let value_added_matrix = Mat::from_fn(value_added.nrows(), m, |row, col| value_added[[row, col]]);
+
+  let (mut transactions, final_demand_symmetric, value_added_symmetric, total_production) =
+    with_threads(threads, || {
+      // transformation matrix, industries by products
+      let transformation = match model {
+        "A" => {
+          let output = Mat::from_fn(p, p, |row, col| if row == col { table.product_output[row] } else { 0.0 });
+          table.make.transpose().partial_piv_lu().solve(output)
+        },
+        "B" => Mat::from_fn(m, p, |i, j| share(table.make[(i, j)], table.industry_output[i])),
+        "C" => {
+          let output = Mat::from_fn(m, m, |row, col| if row == col { table.industry_output[row] } else { 0.0 });
+          table.make.partial_piv_lu().solve(output).transpose().to_owned()
+        },
+        _ => table.market_share(),
+      };
+
+      if matches!(model, "A" | "B") {
+        // product-by-product
+        (
+          &table.use_table * &transformation,
+          final_demand_matrix.clone(),
+          &value_added_matrix * &transformation,
+          table.product_output.clone(),
+        )
+      } else {
+        // industry-by-industry
+        (
+          &transformation * &table.use_table,
+          &transformation * &final_demand_matrix,
+          value_added_matrix.clone(),
+          table.industry_output.clone(),
+        )
+      }
+    })?;
+
+  // negative entries of the transactions and, when the make matrix is inverted, of the transformed accounts
+  let n = transactions.nrows();
+  let count_negatives = |matrix: &Mat<f64>| {
+    matrix.col_iter().flat_map(|col| col.iter().copied()).filter(|&value| value < 0.0).count()
+  };
+  let negative_transactions = count_negatives(&transactions);
+  let negative_entries = negative_transactions + match model {
+    "A" => count_negatives(&value_added_symmetric),
+    "C" => count_negatives(&final_demand_symmetric),
+    _ => 0,
+  };
+  if remove_negatives && negative_transactions > 0 {
+    for col in 0..n {
+      let total: f64 = transactions.col(col).iter().sum();
+      let positive: f64 = transactions.col(col).iter().filter(|&&value| value > 0.0).sum();
+      let scale = if positive > 0.0 { total.max(0.0) / positive } else { 0.0 };
+      for row in 0..n {
+        let value = transactions[(row, col)];
+        transactions[(row, col)] = if value > 0.0 { value * scale } else { 0.0 };
+      }
+    }
+  }
+
+  // sectors are products or industries, depending on the model
+  let labels = if matches!(model, "A" | "B") { &products } else { &industries };
+
+  Ok(list!(
+    intermediate_transactions = set_dimnames(
+      RArray::new_matrix(n, n, |row, col| transactions[(row, col)]),
+      labels,
+      labels
+    )?,
+    total_production = set_dimnames(
+      RArray::new_matrix(1, n, |_, col| total_production[col]),
+      &Null,
+      labels
+    )?,
+    final_demand = set_dimnames(
+      RArray::new_matrix(n, final_demand_symmetric.ncols(), |row, col| final_demand_symmetric[(row, col)]),
+      labels,
+      &category_labels
+    )?,
+    value_added = set_dimnames(
+      RArray::new_matrix(value_added_symmetric.nrows(), n, |row, col| value_added_symmetric[(row, col)]),
+      &component_labels,
+      labels
+    )?,
+    negative_entries = negative_entries as i32
+  ))
+}
+
+// Macro to generate exports.
+// This ensures exported functions are registered with R.
+// See corresponding C code in `entrypoint.c`.
+extendr_module! {
+  mod sut;
+  fn compute_sut_coefficients;
+  fn compute_sut_multipliers;
+  fn compute_symmetric_table;
+}
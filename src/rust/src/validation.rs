@@ -0,0 +1,166 @@
+use extendr_api::prelude::*;
+use rayon::prelude::*;
+
+use crate::labels::set_names;
+use crate::parallel::with_threads;
+
+#[extendr]
+/// @description
+/// Checks the accounting identities and entries of an input-output table.
+///
+/// @param intermediate_transactions
+/// A \eqn{n x n} matrix of intermediate transactions.
+/// @param total_production
+/// A \eqn{1 x n} vector of total production.
+/// @param final_demand_matrix
+/// A \eqn{n x k} matrix of final demand.
+/// @param value_added_matrix
+/// A \eqn{h x n} matrix of value added.
+/// @param tolerance
+/// Relative tolerance of discrepancies.
+/// @param threads
+/// Number of threads. 0 means the session-wide setting.
+/// @param labels
+/// Optional labels of the sectors, attached as names and identifying the sectors of negative entries.
+///
+/// @details
+/// The row identity, \eqn{Z \mathbf{1} + f = x}, and the column identity, \eqn{\mathbf{1}' Z + v' = x}, hold for a
+/// sector when the absolute discrepancy is within `tolerance` times \eqn{\max(1, |x_i|)}
+/// \insertCite{miller_input-output_2009}{fio}. A row is all zero when both its intermediate transactions and final
+/// demand are, and a column when both its intermediate transactions and value added are.
+///
+/// @return
+/// A list with \eqn{1 x n} vectors of row and column totals and discrepancies, logical vectors of identities holding
+/// and of all-zero rows and columns, a list of negative intermediate transactions
+/// (row and column, as labels or 1-based indices, and value) and a matrix of non-finite entries (element, row and column, where elements are
+/// intermediate transactions, total production, final demand and value added, in this order).
+///
+/// @references
+/// \insertAllCited{}
+///
+/// @noRd
+fn validate_table(
+  intermediate_transactions: &[f64],
+  total_production: &[f64],
+  final_demand_matrix: RMatrix<f64>,
+  value_added_matrix: RMatrix<f64>,
+  tolerance: f64,
+  threads: usize,
+  #[default = "NULL"] labels: Nullable<Strings>
+) -> Result<List> {
+
+  // get dimensions
+  let n = total_production.len();
+  if n == 0 {
+    return Err("The table must have at least one sector.".into());
+  }
+  if intermediate_transactions.len() != n * n {
+    return Err("intermediate_transactions must have one row and one column for each sector.".into());
+  }
+  if final_demand_matrix.nrows() != n || value_added_matrix.ncols() != n {
+    return Err("final_demand_matrix and value_added_matrix must have one element for each sector.".into());
+  }
+  let (k, h) = (final_demand_matrix.ncols(), value_added_matrix.nrows());
+  let final_demand = final_demand_matrix.data();
+  let value_added = value_added_matrix.data();
+
+  let (row_totals, column_totals, zero_rows, zero_columns, negatives, non_finite) = with_threads(threads, || {
+    // row identity, Z 1 + f
+    let (row_totals, zero_rows): (Vec<f64>, Vec<bool>) = (0..n)
+      .into_par_iter()
+      .map(|row| {
+        let entries = (0..n)
+          .map(|col| intermediate_transactions[col * n + row])
+          .chain((0..k).map(|col| final_demand[col * n + row]));
+        let zero = entries.clone().all(|value| value == 0.0);
+        (entries.sum::<f64>(), zero)
+      })
+      .unzip();
+
+    // column identity, 1' Z + v'
+    let (column_totals, zero_columns): (Vec<f64>, Vec<bool>) = intermediate_transactions
+      .par_chunks(n)
+      .enumerate()
+      .map(|(col, entries)| {
+        let entries = entries.iter().copied().chain(value_added[col * h..(col + 1) * h].iter().copied());
+        let zero = entries.clone().all(|value| value == 0.0);
+        (entries.sum::<f64>(), zero)
+      })
+      .unzip();
+
+    // negative intermediate transactions, by row and column
+    let negatives: Vec<(usize, usize, f64)> = intermediate_transactions
+      .par_iter()
+      .enumerate()
+      .filter(|(_, &value)| value < 0.0)
+      .map(|(index, &value)| (index % n, index / n, value))
+      .collect();
+
+    // non-finite entries, by element, row and column
+    let elements: [(&[f64], usize); 4] = [
+      (intermediate_transactions, n),
+      (total_production, 1),
+      (final_demand, n),
+      (value_added, h.max(1)),
+    ];
+    let non_finite: Vec<(usize, usize, usize)> = elements
+      .iter()
+      .enumerate()
+      .flat_map(|(element, &(values, nrows))| {
+        values
+          .par_iter()
+          .enumerate()
+          .filter(|(_, value)| !value.is_finite())
+          .map(move |(index, _)| (element, index % nrows, index / nrows))
+          .collect::<Vec<_>>()
+      })
+      .collect();
+
+    (row_totals, column_totals, zero_rows, zero_columns, negatives, non_finite)
+  })?;
+
+  // discrepancies and tolerances
+  let row_discrepancy: Vec<f64> = (0..n).map(|i| row_totals[i] - total_production[i]).collect();
+  let column_discrepancy: Vec<f64> = (0..n).map(|j| column_totals[j] - total_production[j]).collect();
+  let within_tolerance = |discrepancy: f64, total: f64| discrepancy.abs() <= tolerance * total.abs().max(1.0);
+  let row_ok: Vec<bool> = (0..n).map(|i| within_tolerance(row_discrepancy[i], total_production[i])).collect();
+  let column_ok: Vec<bool> = (0..n).map(|j| within_tolerance(column_discrepancy[j], total_production[j])).collect();
+
+  // sectors of negative entries, by label or 1-based index
+  let sectors = |index: fn(&(usize, usize, f64)) -> usize| -> Result<Robj> {
+    match &labels {
+      NotNull(labels) if labels.len() != n => Err(format!("Expected {} labels, got {}.", n, labels.len()).into()),
+      NotNull(labels) => Ok(negatives.iter().map(|entry| labels.elt(index(entry))).collect::<Strings>().into()),
+      Null => Ok(negatives.iter().map(|entry| (index(entry) + 1) as i32).collect::<Vec<i32>>().into()),
+    }
+  };
+
+  Ok(list!(
+    row_totals = set_names(row_totals, &labels)?,
+    column_totals = set_names(column_totals, &labels)?,
+    row_discrepancy = set_names(row_discrepancy, &labels)?,
+    column_discrepancy = set_names(column_discrepancy, &labels)?,
+    row_ok = row_ok,
+    column_ok = column_ok,
+    zero_rows = zero_rows,
+    zero_columns = zero_columns,
+    negative_entries = list!(
+      row = sectors(|entry| entry.0)?,
+      column = sectors(|entry| entry.1)?,
+      value = negatives.iter().map(|entry| entry.2).collect::<Vec<f64>>()
+    ),
+    non_finite_entries = RArray::new_matrix(non_finite.len(), 3, |row, col| match col {
+      0 => (non_finite[row].0 + 1) as f64,
+      1 => (non_finite[row].1 + 1) as f64,
+      _ => (non_finite[row].2 + 1) as f64,
+    })
+  ))
+}
+
+// Macro to generate exports.
+// This ensures exported functions are registered with R.
+// See corresponding C code in `entrypoint.c`.
+extendr_module! {
+  mod validation;
+  fn validate_table;
+}